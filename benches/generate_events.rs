@@ -1,17 +1,20 @@
-use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 
 use criterion::criterion_group;
 use criterion::criterion_main;
 use criterion::BenchmarkId;
 use criterion::Criterion;
+use nexmark_server::generator::nexmark::config::NexmarkConfig;
+use nexmark_server::generator::nexmark::properties::NexmarkProperties;
 use nexmark_server::generator::source::NexmarkSource;
 use nexmark_server::parser::ServerConfig;
 use nexmark_server::run_generators;
-use nexmark_server::NexmarkInterval;
+use nexmark_server::sink::Sink;
+use nexmark_server::{GeneratorControl, NexmarkInterval};
 
 fn event_generation(c: &mut Criterion) {
     let mut group = c.benchmark_group("event_generation");
+    let generator_config = NexmarkConfig::from(NexmarkProperties::default()).unwrap();
     // vary the qps and monitor the time required to send 1,000 events
     for qps in [1_000, 10_000, 100_000, 1_000_000].iter() {
         let nexmark_config = ServerConfig {
@@ -19,17 +22,19 @@ fn event_generation(c: &mut Criterion) {
             ..Default::default()
         };
         let interval = Arc::new(NexmarkInterval::new(&nexmark_config));
-        let running = Arc::new(AtomicBool::new(true));
+        let control = Arc::new(GeneratorControl::new(&nexmark_config));
 
-        let nexmark_source = Arc::new(NexmarkSource::new(&nexmark_config));
+        let nexmark_source: Arc<dyn Sink> = Arc::new(NexmarkSource::new(&nexmark_config));
         group.bench_with_input(BenchmarkId::from_parameter(qps), qps, |b, &_qps| {
             b.to_async(tokio::runtime::Runtime::new().unwrap())
                 .iter(|| {
                     run_generators(
                         nexmark_config.clone(),
+                        generator_config.clone(),
                         nexmark_source.clone(),
-                        running.clone(),
+                        control.clone(),
                         interval.clone(),
+                        None,
                     )
                 });
         });
@@ -44,8 +49,8 @@ fn event_generation(c: &mut Criterion) {
             ..Default::default()
         };
         let interval = Arc::new(NexmarkInterval::new(&nexmark_config));
-        let nexmark_source = Arc::new(NexmarkSource::new(&nexmark_config));
-        let running = Arc::new(AtomicBool::new(true));
+        let nexmark_source: Arc<dyn Sink> = Arc::new(NexmarkSource::new(&nexmark_config));
+        let control = Arc::new(GeneratorControl::new(&nexmark_config));
         group.bench_with_input(
             BenchmarkId::from_parameter(num_gen),
             &num_gen,
@@ -54,9 +59,11 @@ fn event_generation(c: &mut Criterion) {
                     .iter(|| {
                         run_generators(
                             nexmark_config.clone(),
+                            generator_config.clone(),
                             nexmark_source.clone(),
-                            running.clone(),
+                            control.clone(),
                             interval.clone(),
+                            None,
                         )
                     });
             },
@@ -0,0 +1,9 @@
+CREATE TABLE side_input (
+  key BIGINT PRIMARY KEY,
+  value VARCHAR
+) WITH (
+  connector = 'kafka',
+  topic = 'nexmark-side-input',
+  properties.bootstrap.server = '${KAFKA_HOST}:${KAFKA_PORT}',
+  scan.startup.mode = 'earliest'
+) ROW FORMAT JSON;
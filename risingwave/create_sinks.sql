@@ -190,6 +190,19 @@ CREATE SINK nexmark_q12 AS
     WITH ( connector = 'blackhole', type = 'append-only', force_append_only = 'true');
 
 
+-- requires create_side_input.sql
+CREATE SINK nexmark_q13 AS
+    SELECT B.auction,
+           B.bidder,
+           B.price,
+           B.date_time,
+           S.value
+    FROM bid B
+             JOIN side_input FOR SYSTEM_TIME AS OF PROCTIME() S
+                  ON mod(B.auction, 10000) = S.key
+    WITH ( connector = 'blackhole', type = 'append-only');
+
+
 CREATE SINK nexmark_q14 AS
     SELECT auction,
            bidder,
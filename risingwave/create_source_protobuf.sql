@@ -0,0 +1,10 @@
+-- The columns are read from the `nexmark.Event` message of `proto/nexmark.proto`, whose descriptor set is generated with
+-- `protoc --include_imports --descriptor_set_out=nexmark.pb proto/nexmark.proto`, at `PROTO_DESCRIPTOR_PATH`.
+-- Run `nexmark-server --format protobuf` with `SEPARATE_TOPICS=false` to produce the events.
+CREATE SOURCE nexmark
+WITH (
+  connector = 'kafka',
+  topic = 'nexmark-events',
+  properties.bootstrap.server = '${KAFKA_HOST}:${KAFKA_PORT}',
+  scan.startup.mode = 'earliest'
+) ROW FORMAT PROTOBUF MESSAGE 'nexmark.Event' ROW SCHEMA LOCATION 'file://${PROTO_DESCRIPTOR_PATH}';
@@ -8,6 +8,7 @@ DROP SINK nexmark_q7;
 DROP SINK nexmark_q8;
 DROP SINK nexmark_q9;
 DROP SINK nexmark_q10;
+DROP SINK nexmark_q13;
 DROP SINK nexmark_q14;
 DROP SINK nexmark_q15;
 DROP SINK nexmark_q16;
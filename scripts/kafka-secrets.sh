@@ -0,0 +1,45 @@
+#!/bin/bash
+# Generates the certificates and credentials of the kafka-secure broker of services.yml into
+# ./secrets: a CA signing the broker and client certificates, the keystores of the broker, and
+# the JAAS config of the SASL/PLAIN and SCRAM users.
+set -euo pipefail
+
+DIR=${1:-secrets}
+PASSWORD=nexmark-secret
+DAYS=365
+
+mkdir -p "$DIR"
+cd "$DIR"
+
+openssl req -x509 -newkey rsa:2048 -nodes -days $DAYS -subj "/CN=nexmark-ca" \
+    -keyout ca.key -out ca.crt
+
+# the broker is reached as localhost from the host, and as kafka-secure from the containers
+openssl req -newkey rsa:2048 -nodes -subj "/CN=kafka-secure" -keyout broker.key -out broker.csr
+printf "subjectAltName=DNS:localhost,DNS:kafka-secure,IP:127.0.0.1" > broker.ext
+openssl x509 -req -in broker.csr -CA ca.crt -CAkey ca.key -CAcreateserial -days $DAYS \
+    -extfile broker.ext -out broker.crt
+openssl pkcs12 -export -in broker.crt -inkey broker.key -certfile ca.crt -name kafka-secure \
+    -passout pass:$PASSWORD -out broker.keystore.p12
+
+rm -f broker.truststore.p12
+keytool -importcert -noprompt -alias nexmark-ca -file ca.crt -storetype PKCS12 \
+    -keystore broker.truststore.p12 -storepass $PASSWORD
+
+# the client certificate of nexmark-server, for the listener requiring TLS client authentication
+openssl req -newkey rsa:2048 -nodes -subj "/CN=nexmark" -keyout client.key -out client.csr
+openssl x509 -req -in client.csr -CA ca.crt -CAkey ca.key -CAcreateserial -days $DAYS \
+    -out client.crt
+
+echo -n $PASSWORD > credentials
+
+cat > kafka_server_jaas.conf <<JAAS
+KafkaServer {
+    org.apache.kafka.common.security.plain.PlainLoginModule required
+    user_nexmark="$PASSWORD";
+    org.apache.kafka.common.security.scram.ScramLoginModule required;
+};
+JAAS
+
+rm -f broker.csr broker.ext client.csr ca.srl
+chmod 644 ./*
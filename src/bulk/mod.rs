@@ -0,0 +1,208 @@
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+use tokio::runtime::Handle;
+
+use crate::checkpoint::Checkpointer;
+use crate::generator::nexmark::config::NexmarkConfig;
+use crate::generator::nexmark::event::Event;
+use crate::generator::reorder::DisorderStats;
+use crate::pacer::MAX_BATCH_SIZE;
+use crate::parser::ServerConfig;
+use crate::sink::{DestinationCount, Distribution, Sink, SinkMetrics};
+use crate::{
+    batch_failed, flush, generator_config, new_generator, GeneratorControl, PAUSE_CHECK_INTERVAL,
+};
+
+/// How long a generator waits for the sink to deliver its backlog once it is done, which may be
+/// the whole producer queue in the bulk mode.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What was delivered by a bulk run.
+#[derive(Clone, Debug)]
+pub struct BulkSummary {
+    pub metrics: SinkMetrics,
+    pub destinations: Vec<DestinationCount>,
+    pub disorder: DisorderStats,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for BulkSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.elapsed.as_secs_f64();
+        writeln!(
+            f,
+            "Delivered {} of the {} events sent ({} bytes, {} errors) in {:.3}s: {:.0} events/s, {:.1} MB/s",
+            self.metrics.events_delivered,
+            self.metrics.events_sent,
+            self.metrics.bytes_sent,
+            self.metrics.send_errors,
+            secs,
+            self.metrics.events_delivered as f64 / secs,
+            self.metrics.bytes_sent as f64 / secs / 1_000_000.0,
+        )?;
+        writeln!(f, "Disorder of the event times: {}", self.disorder)?;
+        write!(f, "{}", Distribution(&self.destinations))
+    }
+}
+
+/// Generates the events as fast as the sink accepts them, to preload it before the system under
+/// test is started.
+///
+/// Unlike `run_generators`, every generator runs on its own thread without pacing, so that the
+/// generation, serialization and sending of the events use all the cores, and only pauses and
+/// stops through `control`.
+pub async fn run_bulk(
+    server_config: ServerConfig,
+    nexmark_config: NexmarkConfig,
+    sink: Arc<dyn Sink>,
+    control: Arc<GeneratorControl>,
+    checkpointer: Option<Arc<Checkpointer>>,
+) -> BulkSummary {
+    let start_time = Instant::now();
+    let generator_config = generator_config(&server_config, nexmark_config);
+    let handle = Handle::current();
+    let threads: Vec<_> = (0..server_config.num_event_generators)
+        .map(|generator_idx| {
+            let generator_config = generator_config.clone();
+            let control = control.clone();
+            let sink = sink.clone();
+            let handle = handle.clone();
+            let checkpointer = checkpointer.clone();
+            thread::Builder::new()
+                .name(format!("generator-{}", generator_idx))
+                .spawn(move || {
+                    let mut generator =
+                        new_generator(generator_config, generator_idx, checkpointer.as_deref());
+                    let mut checkpoint_time = Instant::now();
+                    while control.is_running() {
+                        if control.is_paused() {
+                            thread::sleep(PAUSE_CHECK_INTERVAL);
+                            continue;
+                        }
+                        let (skip_person, skip_auction, skip_bid) = control.skipped_event_types();
+                        generator.set_skipped_event_types(skip_person, skip_auction, skip_bid);
+                        let events: Vec<Event> = (0..MAX_BATCH_SIZE)
+                            .map_while(|_| generator.next_event())
+                            .collect();
+                        let finished = (events.len() as u64) < MAX_BATCH_SIZE;
+                        control.record_generated(generator_idx, &events);
+                        if !events.is_empty() {
+                            if let Err(err) =
+                                handle.block_on(sink.send_batch(generator_idx, events))
+                            {
+                                batch_failed(generator_idx, err, checkpointer.as_deref());
+                            }
+                        }
+                        if finished {
+                            break;
+                        }
+                        if let Some(checkpointer) = &checkpointer {
+                            if checkpoint_time.elapsed() >= checkpointer.interval() {
+                                flush(
+                                    sink.as_ref(),
+                                    generator_idx,
+                                    &generator,
+                                    Some(checkpointer),
+                                    FLUSH_TIMEOUT,
+                                );
+                                checkpoint_time = Instant::now();
+                            }
+                        }
+                    }
+                    flush(
+                        sink.as_ref(),
+                        generator_idx,
+                        &generator,
+                        checkpointer.as_deref(),
+                        FLUSH_TIMEOUT,
+                    );
+                    control.record_disorder(generator.disorder_stats());
+                    info!("Producer_{} is done", generator_idx);
+                })
+                .expect("Failed to spawn generator thread")
+        })
+        .collect();
+    // joining blocks, so it is kept off the runtime which drives the timers of the sink
+    tokio::task::spawn_blocking(move || {
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    })
+    .await
+    .unwrap();
+    if let Err(err) = sink.close().await {
+        error!("Failed to close the sink: {:#}", err);
+    }
+    BulkSummary {
+        metrics: sink.metrics(),
+        destinations: sink.destinations(),
+        disorder: control.disorder_stats(),
+        elapsed: start_time.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::nexmark::properties::NexmarkProperties;
+    use crate::parser::FileSinkConfig;
+    use crate::sink::file::FileSink;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_run_bulk() {
+        let dir = tempfile::tempdir().unwrap();
+        let server_config = ServerConfig {
+            bulk: true,
+            max_events: 10_000,
+            num_event_generators: 2,
+            file_sink: FileSinkConfig {
+                output_dir: dir.path().to_path_buf(),
+                separate_files: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let nexmark_config = NexmarkConfig::from(NexmarkProperties::default()).unwrap();
+        let sink = Arc::new(FileSink::new(
+            &server_config.file_sink,
+            2,
+            Default::default(),
+        ));
+        sink.open().await.unwrap();
+        let summary = run_bulk(
+            server_config.clone(),
+            nexmark_config,
+            sink,
+            Arc::new(GeneratorControl::new(&server_config)),
+            None,
+        )
+        .await;
+
+        assert_eq!(summary.metrics.events_sent, 10_000);
+        // 1 person, 3 auctions and 46 bids out of every 50 events, where the generators take turns
+        assert_eq!(
+            summary
+                .destinations
+                .iter()
+                .map(|d| (d.topic.as_str(), d.partition, d.events))
+                .collect::<Vec<_>>(),
+            vec![
+                ("auction", 0, 200),
+                ("auction", 1, 400),
+                ("bid", 0, 4600),
+                ("bid", 1, 4600),
+                ("person", 0, 200)
+            ]
+        );
+        let report = summary.to_string();
+        assert!(
+            report.contains("auction: 600 events over 2 partitions, the largest with 1.33x the mean\n  auction[0]: 200 events (33.3%)\n"),
+            "{}",
+            report
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context, Result};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::generator::reorder::EmitKey;
+use crate::generator::NexmarkGenerator;
+use crate::parser::{Durability, ServerConfig, SinkType};
+use crate::sink::Sink;
+
+/// The progress of the generators, as persisted in the checkpoint file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The generators take turns on the event ids, so a run can only be resumed with as many.
+    pub num_event_generators: usize,
+    pub max_events: u64,
+    /// The events generated or skipped by every generator, all of them delivered.
+    pub local_events_so_far: Vec<u64>,
+    /// The last event delivered by every generator that emits its events out of order, which
+    /// may be after some of the events it still delayed.
+    #[serde(default)]
+    pub last_emitted: Vec<Option<EmitKey>>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read checkpoint {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("invalid checkpoint {}", path.display()))
+    }
+}
+
+/// Persists the progress of every generator to the `--checkpoint-file` once the brokers
+/// acknowledged its events, so that a crashed run can be resumed with `--resume` without gaps.
+///
+/// Resuming is at-least-once: the events sent after the last checkpoint of a generator are sent
+/// again, so the generators checkpoint every `--checkpoint-interval-secs` and when they stop.
+#[derive(Debug)]
+pub struct Checkpointer {
+    path: PathBuf,
+    interval: Duration,
+    checkpoint: Mutex<Checkpoint>,
+    /// The generators that may have lost events, whose checkpoint no longer moves.
+    poisoned: Vec<AtomicBool>,
+}
+
+impl Checkpointer {
+    /// Returns `None` if checkpoints are disabled. Otherwise loads the checkpoint file if
+    /// `--resume` is set, or writes the starting point of the generators to it.
+    pub fn new(config: &ServerConfig) -> Result<Option<Self>> {
+        let path = match &config.checkpoint.checkpoint_file {
+            Some(path) => path.clone(),
+            None => return Ok(None),
+        };
+        // with acks=0 a delivery report doesn't mean that the brokers have the events
+        ensure!(
+            config.durability != Durability::Fast,
+            "--checkpoint-file requires --durability idempotent or transactional"
+        );
+        ensure!(
+            config.checkpoint.checkpoint_interval_secs > 0,
+            "--checkpoint-interval-secs should be greater than 0"
+        );
+        let checkpoint = if config.checkpoint.resume {
+            // the file sink recreates its files, so the events before the checkpoint would be lost
+            ensure!(
+                config.sink == SinkType::Kafka,
+                "--resume is only supported with --sink kafka"
+            );
+            let checkpoint = Checkpoint::load(&path)?;
+            ensure!(
+                checkpoint.num_event_generators == config.num_event_generators,
+                "the checkpoint was written by {} generators, got --num-event-generators {}",
+                checkpoint.num_event_generators,
+                config.num_event_generators
+            );
+            ensure!(
+                checkpoint.max_events == config.max_events,
+                "the checkpoint was written with --max-events {}, got {}",
+                checkpoint.max_events,
+                config.max_events
+            );
+            info!(
+                "Resuming from {}: {:?}",
+                path.display(),
+                checkpoint.local_events_so_far
+            );
+            checkpoint
+        } else {
+            let generator_num = config.num_event_generators as u64;
+            Checkpoint {
+                num_event_generators: config.num_event_generators,
+                max_events: config.max_events,
+                local_events_so_far: (0..generator_num)
+                    .map(|index| {
+                        NexmarkGenerator::local_events_at(config.start_event, generator_num, index)
+                    })
+                    .collect(),
+                last_emitted: vec![None; config.num_event_generators],
+            }
+        };
+        write_atomically(&path, &checkpoint)?;
+        Ok(Some(Self {
+            path,
+            interval: Duration::from_secs(config.checkpoint.checkpoint_interval_secs),
+            checkpoint: Mutex::new(checkpoint),
+            poisoned: (0..config.num_event_generators)
+                .map(|_| AtomicBool::new(false))
+                .collect(),
+        }))
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns where a generator starts, or resumes.
+    pub fn local_events_so_far(&self, generator_idx: usize) -> u64 {
+        self.checkpoint.lock().unwrap().local_events_so_far[generator_idx]
+    }
+
+    /// Returns the last event a generator emitted out of order, if any.
+    pub fn last_emitted(&self, generator_idx: usize) -> Option<EmitKey> {
+        let checkpoint = self.checkpoint.lock().unwrap();
+        checkpoint
+            .last_emitted
+            .get(generator_idx)
+            .copied()
+            .flatten()
+    }
+
+    /// Stops checkpointing a generator after some of its events failed to be sent or delivered,
+    /// so that a resumed run sends them again from its last checkpoint.
+    pub fn poison(&self, generator_idx: usize) {
+        if !self.poisoned[generator_idx].swap(true, Ordering::Relaxed) {
+            error!(
+                "Producer_{} lost events, its checkpoint stays at {}",
+                generator_idx,
+                self.local_events_so_far(generator_idx)
+            );
+        }
+    }
+
+    /// Waits until the events sent by a generator are delivered, then persists its progress.
+    /// Nothing is persisted if some of them may be lost, then or at any time before.
+    pub fn flush_and_save(
+        &self,
+        sink: &dyn Sink,
+        generator_idx: usize,
+        local_events_so_far: u64,
+        last_emitted: Option<EmitKey>,
+        timeout: Duration,
+    ) -> Result<()> {
+        if self.poisoned[generator_idx].load(Ordering::Relaxed) {
+            bail!(
+                "Producer_{} lost events, its checkpoint is not updated",
+                generator_idx
+            );
+        }
+        // the sink only reports a failed delivery once
+        if let Err(err) = sink.flush(generator_idx, timeout) {
+            self.poison(generator_idx);
+            return Err(err);
+        }
+        let mut checkpoint = self.checkpoint.lock().unwrap();
+        checkpoint.local_events_so_far[generator_idx] = local_events_so_far;
+        let num_event_generators = checkpoint.num_event_generators;
+        checkpoint.last_emitted.resize(num_event_generators, None);
+        checkpoint.last_emitted[generator_idx] = last_emitted;
+        write_atomically(&self.path, &checkpoint)
+    }
+}
+
+/// Replaces the checkpoint file, so that it is never left half written by a crash.
+fn write_atomically(path: &Path, checkpoint: &Checkpoint) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let write = || -> Result<()> {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(serde_json::to_string_pretty(checkpoint)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    };
+    write().with_context(|| format!("failed to write checkpoint {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::generator::nexmark::event::Event;
+    use crate::parser::CheckpointConfig;
+    use crate::sink::SinkMetrics;
+
+    /// Fails to deliver the events of the generator 2, which is only reported by the first flush.
+    #[derive(Default)]
+    struct FailingSink {
+        reported: AtomicBool,
+    }
+
+    #[async_trait]
+    impl Sink for FailingSink {
+        async fn open(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn send_batch(&self, _generator_idx: usize, _events: Vec<Event>) -> Result<()> {
+            Ok(())
+        }
+
+        fn flush(&self, generator_idx: usize, _timeout: Duration) -> Result<()> {
+            ensure!(
+                generator_idx != 2 || self.reported.swap(true, Ordering::Relaxed),
+                "failed to deliver"
+            );
+            Ok(())
+        }
+
+        async fn close(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn metrics(&self) -> SinkMetrics {
+            SinkMetrics::default()
+        }
+    }
+
+    #[test]
+    fn test_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint.json");
+        let mut config = ServerConfig {
+            start_event: 10,
+            num_event_generators: 3,
+            durability: Durability::Idempotent,
+            checkpoint: CheckpointConfig {
+                checkpoint_file: Some(path.clone()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let checkpointer = Checkpointer::new(&config).unwrap().unwrap();
+        // the first events of the generators are 12, 10 and 11
+        assert_eq!(
+            Checkpoint::load(&path).unwrap().local_events_so_far,
+            [4, 3, 3]
+        );
+
+        let timeout = Duration::from_secs(1);
+        let sink = FailingSink::default();
+        checkpointer
+            .flush_and_save(&sink, 1, 20, None, timeout)
+            .unwrap();
+        assert!(checkpointer
+            .flush_and_save(&sink, 2, 20, None, timeout)
+            .is_err());
+        // the lost events are not skipped by the next checkpoints
+        assert!(checkpointer
+            .flush_and_save(&sink, 2, 30, None, timeout)
+            .is_err());
+
+        config.checkpoint.resume = true;
+        let resumed = Checkpointer::new(&config).unwrap().unwrap();
+        assert_eq!(
+            (0..3)
+                .map(|i| resumed.local_events_so_far(i))
+                .collect::<Vec<_>>(),
+            [4, 20, 3]
+        );
+        config.num_event_generators = 4;
+        assert!(Checkpointer::new(&config).is_err());
+        config.num_event_generators = 3;
+        config.durability = Durability::Fast;
+        assert!(Checkpointer::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_resume_file_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ServerConfig {
+            sink: SinkType::File,
+            durability: Durability::Idempotent,
+            checkpoint: CheckpointConfig {
+                checkpoint_file: Some(dir.path().join("checkpoint.json")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(Checkpointer::new(&config).unwrap().is_some());
+        config.checkpoint.resume = true;
+        let err = Checkpointer::new(&config).unwrap_err();
+        assert!(err.to_string().contains("--sink kafka"), "{}", err);
+    }
+}
@@ -0,0 +1,509 @@
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, bail, Context, Result};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request};
+use hyper_tls::HttpsConnector;
+use log::info;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::format::SerializeOptions;
+use crate::generator::nexmark::event::{Auction, Bid, Event, Person};
+use crate::generator::nexmark::utils::{milli_ts_to_rfc3339, milli_ts_to_timestamp_string};
+use crate::parser::TimestampFormat;
+
+/// The first byte of the Confluent wire format, followed by the 4 bytes of the schema id.
+const MAGIC_BYTE: u8 = 0;
+
+/// Encodes the events as Avro datums in the Confluent wire format, i.e. prefixed by the id of
+/// their schema in the schema registry.
+pub struct AvroEncoder {
+    combined: bool,
+    options: SerializeOptions,
+    person: serde_json::Value,
+    auction: serde_json::Value,
+    bid: serde_json::Value,
+    event: serde_json::Value,
+    schema_ids: OnceLock<SchemaIds>,
+}
+
+/// The registry ids of the schemas of each event type. They are all the same when the events are
+/// combined in one topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SchemaIds {
+    pub person: u32,
+    pub auction: u32,
+    pub bid: u32,
+}
+
+/// The registry subjects the schemas are registered under, see `AvroEncoder::register`.
+pub enum SchemaSubjects<'a> {
+    Combined(&'a str),
+    Separate {
+        person: &'a str,
+        auction: &'a str,
+        bid: &'a str,
+    },
+}
+
+impl AvroEncoder {
+    pub fn new(combined: bool, options: SerializeOptions) -> Self {
+        let [person, auction, bid, event] = schemas(options);
+        Self {
+            combined,
+            options,
+            person,
+            auction,
+            bid,
+            event,
+            schema_ids: OnceLock::new(),
+        }
+    }
+
+    /// Registers the schemas, following the `TopicNameStrategy` of the Confluent serializers,
+    /// i.e. under the `{topic}-value` subjects. Events can only be encoded afterwards.
+    pub async fn register(
+        &self,
+        registry: &SchemaRegistry,
+        subjects: SchemaSubjects<'_>,
+    ) -> Result<SchemaIds> {
+        let ids = match subjects {
+            SchemaSubjects::Combined(topic) => {
+                let id = registry.register(topic, &self.event).await?;
+                SchemaIds {
+                    person: id,
+                    auction: id,
+                    bid: id,
+                }
+            }
+            SchemaSubjects::Separate {
+                person,
+                auction,
+                bid,
+            } => SchemaIds {
+                person: registry.register(person, &self.person).await?,
+                auction: registry.register(auction, &self.auction).await?,
+                bid: registry.register(bid, &self.bid).await?,
+            },
+        };
+        self.set_schema_ids(ids);
+        Ok(ids)
+    }
+
+    /// Uses already known schema ids instead of registering the schemas.
+    pub fn set_schema_ids(&self, ids: SchemaIds) {
+        let _ = self.schema_ids.set(ids);
+    }
+
+    pub fn encode(&self, event: Event) -> Result<Vec<u8>> {
+        let ids = self
+            .schema_ids
+            .get()
+            .ok_or_else(|| anyhow!("the avro schemas are not registered"))?;
+        let id = match &event {
+            Event::Person(_) => ids.person,
+            Event::Auction(_) => ids.auction,
+            Event::Bid(_) => ids.bid,
+        };
+        let mut payload = Vec::with_capacity(256);
+        payload.push(MAGIC_BYTE);
+        payload.extend_from_slice(&id.to_be_bytes());
+        if self.combined {
+            // The `CombinedEvent` record: the event type, then one `["null", X]` union per event
+            // type, of which only the one of the event is set.
+            let event_type = match &event {
+                Event::Person(_) => 0,
+                Event::Auction(_) => 1,
+                Event::Bid(_) => 2,
+            };
+            write_long(&mut payload, event_type);
+            for i in 0..3 {
+                write_long(&mut payload, (i == event_type) as i64);
+                if i == event_type {
+                    write_event(&mut payload, &event, self.options);
+                }
+            }
+        } else {
+            write_event(&mut payload, &event, self.options);
+        }
+        Ok(payload)
+    }
+}
+
+fn write_event(buf: &mut Vec<u8>, event: &Event, options: SerializeOptions) {
+    match event {
+        Event::Person(p) => write_person(buf, p, options),
+        Event::Auction(a) => write_auction(buf, a, options),
+        Event::Bid(b) => write_bid(buf, b, options),
+    }
+}
+
+fn write_person(buf: &mut Vec<u8>, p: &Person, options: SerializeOptions) {
+    write_long(buf, p.id as i64);
+    write_string(buf, &p.name);
+    write_string(buf, &p.email_address);
+    write_string(buf, &p.credit_card);
+    write_string(buf, &p.city);
+    write_string(buf, &p.state);
+    write_timestamp(buf, p.date_time, options);
+    write_string(buf, &p.extra);
+}
+
+fn write_auction(buf: &mut Vec<u8>, a: &Auction, options: SerializeOptions) {
+    write_long(buf, a.id as i64);
+    write_string(buf, &a.item_name);
+    write_string(buf, &a.description);
+    write_long(buf, a.initial_bid as i64);
+    write_long(buf, a.reserve as i64);
+    write_timestamp(buf, a.date_time, options);
+    write_timestamp(buf, a.expires, options);
+    write_long(buf, a.seller as i64);
+    write_long(buf, a.category as i64);
+    write_string(buf, &a.extra);
+}
+
+fn write_bid(buf: &mut Vec<u8>, b: &Bid, options: SerializeOptions) {
+    write_long(buf, b.auction as i64);
+    write_long(buf, b.bidder as i64);
+    write_long(buf, b.price as i64);
+    write_string(buf, &b.channel);
+    write_string(buf, &b.url);
+    write_timestamp(buf, b.date_time, options);
+    write_string(buf, &b.extra);
+}
+
+/// Writes a millisecond timestamp with the type declared by `timestamp_schema`.
+fn write_timestamp(buf: &mut Vec<u8>, milli_ts: usize, options: SerializeOptions) {
+    match options.timestamp_format {
+        TimestampFormat::String => write_string(buf, &milli_ts_to_timestamp_string(milli_ts)),
+        TimestampFormat::EpochMillis => write_long(buf, milli_ts as i64),
+        TimestampFormat::EpochMicros => write_long(buf, milli_ts as i64 * 1000),
+        TimestampFormat::Rfc3339 => write_string(buf, &milli_ts_to_rfc3339(milli_ts)),
+    }
+}
+
+/// Writes an Avro `long`: zig-zag encoded, then as a variable-length integer.
+fn write_long(buf: &mut Vec<u8>, n: i64) {
+    let mut n = ((n << 1) ^ (n >> 63)) as u64;
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+/// Writes an Avro `string`: its length in bytes, then its UTF-8 bytes.
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    write_long(buf, s.len() as i64);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// Returns the schemas of `Person`, `Auction`, `Bid` and `CombinedEvent`, with the fields in the
+/// same order as the structs.
+fn schemas(options: SerializeOptions) -> [serde_json::Value; 4] {
+    let timestamp = timestamp_schema(options.timestamp_format);
+    let record = |name: &str, fields: &[(&'static str, &serde_json::Value)]| {
+        json!({
+            "type": "record",
+            "name": name,
+            "namespace": "nexmark",
+            "fields": fields
+                .iter()
+                .map(|(name, ty)| json!({"name": options.field_name(name), "type": ty}))
+                .collect::<Vec<_>>(),
+        })
+    };
+    let person = record(
+        "Person",
+        &[
+            ("id", &json!("long")),
+            ("name", &json!("string")),
+            ("email_address", &json!("string")),
+            ("credit_card", &json!("string")),
+            ("city", &json!("string")),
+            ("state", &json!("string")),
+            ("date_time", &timestamp),
+            ("extra", &json!("string")),
+        ],
+    );
+    let auction = record(
+        "Auction",
+        &[
+            ("id", &json!("long")),
+            ("item_name", &json!("string")),
+            ("description", &json!("string")),
+            ("initial_bid", &json!("long")),
+            ("reserve", &json!("long")),
+            ("date_time", &timestamp),
+            ("expires", &timestamp),
+            ("seller", &json!("long")),
+            ("category", &json!("long")),
+            ("extra", &json!("string")),
+        ],
+    );
+    let bid = record(
+        "Bid",
+        &[
+            ("auction", &json!("long")),
+            ("bidder", &json!("long")),
+            ("price", &json!("long")),
+            ("channel", &json!("string")),
+            ("url", &json!("string")),
+            ("date_time", &timestamp),
+            ("extra", &json!("string")),
+        ],
+    );
+    let event = json!({
+        "type": "record",
+        "name": "CombinedEvent",
+        "namespace": "nexmark",
+        "fields": [
+            {"name": "event_type", "type": "long"},
+            {"name": "person", "type": ["null", person], "default": null},
+            {"name": "auction", "type": ["null", auction], "default": null},
+            {"name": "bid", "type": ["null", bid], "default": null},
+        ],
+    });
+    [person, auction, bid, event]
+}
+
+fn timestamp_schema(format: TimestampFormat) -> serde_json::Value {
+    match format {
+        TimestampFormat::String | TimestampFormat::Rfc3339 => json!("string"),
+        TimestampFormat::EpochMillis => json!({"type": "long", "logicalType": "timestamp-millis"}),
+        TimestampFormat::EpochMicros => json!({"type": "long", "logicalType": "timestamp-micros"}),
+    }
+}
+
+/// A client of the REST API of a Confluent-compatible schema registry.
+pub struct SchemaRegistry {
+    url: String,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+#[derive(Deserialize)]
+struct RegisterResponse {
+    id: u32,
+}
+
+impl SchemaRegistry {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            client: Client::builder().build(HttpsConnector::new()),
+        }
+    }
+
+    /// Registers the schema as the value of a topic, returning its id. Registering the same
+    /// schema again returns the same id.
+    pub async fn register(&self, topic: &str, schema: &serde_json::Value) -> Result<u32> {
+        let subject = format!("{}-value", topic);
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}/subjects/{}/versions", self.url, subject))
+            .header("Content-Type", "application/vnd.schemaregistry.v1+json")
+            .body(Body::from(
+                json!({ "schema": schema.to_string() }).to_string(),
+            ))?;
+        let response = self
+            .client
+            .request(request)
+            .await
+            .with_context(|| format!("failed to register the schema of {}", subject))?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        if !status.is_success() {
+            bail!(
+                "failed to register the schema of {}: {} {}",
+                subject,
+                status,
+                String::from_utf8_lossy(&body)
+            );
+        }
+        let id = serde_json::from_slice::<RegisterResponse>(&body)?.id;
+        info!("Registered the avro schema of {} with id {}", subject, id);
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::generator::test_events;
+
+    fn epoch_millis() -> SerializeOptions {
+        SerializeOptions {
+            timestamp_format: TimestampFormat::EpochMillis,
+            ..Default::default()
+        }
+    }
+
+    /// Reads back the datums written by `AvroEncoder`.
+    struct Reader<'a>(&'a [u8]);
+
+    impl Reader<'_> {
+        fn long(&mut self) -> i64 {
+            let (mut n, mut shift) = (0u64, 0);
+            loop {
+                let byte = self.0[0];
+                self.0 = &self.0[1..];
+                n |= ((byte & 0x7f) as u64) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    return (n >> 1) as i64 ^ -((n & 1) as i64);
+                }
+            }
+        }
+
+        fn id(&mut self) -> usize {
+            self.long() as usize
+        }
+
+        fn string(&mut self) -> String {
+            let len = self.long() as usize;
+            let s = String::from_utf8(self.0[..len].to_vec()).unwrap();
+            self.0 = &self.0[len..];
+            s
+        }
+
+        fn event(&mut self, event_type: i64) -> Event {
+            match event_type {
+                0 => Event::Person(Person {
+                    id: self.id(),
+                    name: self.string(),
+                    email_address: self.string(),
+                    credit_card: self.string(),
+                    city: self.string(),
+                    state: self.string(),
+                    date_time: self.id(),
+                    extra: self.string(),
+                }),
+                1 => Event::Auction(Auction {
+                    id: self.id(),
+                    item_name: self.string(),
+                    description: self.string(),
+                    initial_bid: self.id(),
+                    reserve: self.id(),
+                    date_time: self.id(),
+                    expires: self.id(),
+                    seller: self.id(),
+                    category: self.id(),
+                    extra: self.string(),
+                }),
+                _ => Event::Bid(Bid {
+                    auction: self.id(),
+                    bidder: self.id(),
+                    price: self.id(),
+                    channel: self.string(),
+                    url: self.string(),
+                    date_time: self.id(),
+                    extra: self.string(),
+                }),
+            }
+        }
+    }
+
+    fn event_type(event: &Event) -> i64 {
+        match event {
+            Event::Person(_) => 0,
+            Event::Auction(_) => 1,
+            Event::Bid(_) => 2,
+        }
+    }
+
+    #[test]
+    fn test_write_long() {
+        for (n, bytes) in [
+            (0, vec![0x00]),
+            (-1, vec![0x01]),
+            (1, vec![0x02]),
+            (-64, vec![0x7f]),
+            (64, vec![0x80, 0x01]),
+        ] {
+            let mut buf = vec![];
+            write_long(&mut buf, n);
+            assert_eq!(buf, bytes);
+        }
+    }
+
+    /// Answers every request with the next schema id, starting from 1.
+    async fn mock_registry() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for id in 1.. {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 16384];
+                let _ = stream.read(&mut buf).await.unwrap();
+                let body = format!("{{\"id\":{}}}", id);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_register_and_encode_separate() {
+        let registry = SchemaRegistry::new(&mock_registry().await);
+        let encoder = AvroEncoder::new(false, epoch_millis());
+        let subjects = SchemaSubjects::Separate {
+            person: "nexmark-person",
+            auction: "nexmark-auction",
+            bid: "nexmark-bid",
+        };
+        let ids = encoder.register(&registry, subjects).await.unwrap();
+        assert_eq!(
+            ids,
+            SchemaIds {
+                person: 1,
+                auction: 2,
+                bid: 3
+            }
+        );
+
+        for event in test_events(50) {
+            let payload = encoder.encode(event.clone()).unwrap();
+            assert_eq!(payload[0], MAGIC_BYTE);
+            let id = u32::from_be_bytes(payload[1..5].try_into().unwrap());
+            assert_eq!(id as i64, event_type(&event) + 1);
+            let mut reader = Reader(&payload[5..]);
+            assert_eq!(reader.event(event_type(&event)), event);
+            assert!(reader.0.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_encode_combined() {
+        let encoder = AvroEncoder::new(true, epoch_millis());
+        assert!(encoder.encode(test_events(1).remove(0)).is_err());
+        encoder.set_schema_ids(SchemaIds {
+            person: 42,
+            auction: 42,
+            bid: 42,
+        });
+        for event in test_events(50) {
+            let payload = encoder.encode(event.clone()).unwrap();
+            assert_eq!(u32::from_be_bytes(payload[1..5].try_into().unwrap()), 42);
+            let mut reader = Reader(&payload[5..]);
+            let event_type = reader.long();
+            assert_eq!(event_type, self::event_type(&event));
+            for i in 0..3 {
+                let branch = reader.long();
+                assert_eq!(branch, (i == event_type) as i64);
+                if branch == 1 {
+                    assert_eq!(reader.event(event_type), event);
+                }
+            }
+            assert!(reader.0.is_empty());
+        }
+    }
+}
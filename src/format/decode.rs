@@ -0,0 +1,217 @@
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use serde_json::{Map, Value};
+
+use crate::format::SerializeOptions;
+use crate::generator::nexmark::event::{Auction, Bid, Event, Person};
+use crate::generator::nexmark::utils::{rfc3339_to_milli_ts, timestamp_string_to_milli_ts};
+use crate::parser::TimestampFormat;
+
+/// Parses a JSON record written by `EventView`, checking that it has exactly the fields of its
+/// layout with the types and names selected by `options`.
+///
+/// `event_type` is the type of the events of a separate topic, or `None` for the combined layout.
+pub fn decode_json(
+    payload: &[u8],
+    event_type: Option<&str>,
+    options: SerializeOptions,
+) -> Result<Event> {
+    let value: Value = serde_json::from_slice(payload).context("invalid JSON")?;
+    if let Some(event_type) = event_type {
+        return decode_event(&value, event_type, options);
+    }
+    let record = Record::new(&value, 4, options)?;
+    let event_type = match record.raw("event_type")?.as_u64() {
+        Some(0) => "person",
+        Some(1) => "auction",
+        Some(2) => "bid",
+        _ => bail!("event_type should be 0, 1 or 2"),
+    };
+    for other in ["person", "auction", "bid"] {
+        ensure!(
+            other == event_type || record.raw(other)?.is_null(),
+            "{} should be null in a {} event",
+            other,
+            event_type
+        );
+    }
+    decode_event(record.raw(event_type)?, event_type, options)
+}
+
+fn decode_event(value: &Value, event_type: &str, options: SerializeOptions) -> Result<Event> {
+    let event = match event_type {
+        "person" => {
+            let r = Record::new(value, 8, options)?;
+            Event::Person(Person {
+                id: r.number("id")?,
+                name: r.string("name")?,
+                email_address: r.string("email_address")?,
+                credit_card: r.string("credit_card")?,
+                city: r.string("city")?,
+                state: r.string("state")?,
+                date_time: r.timestamp("date_time")?,
+                extra: r.string("extra")?,
+            })
+        }
+        "auction" => {
+            let r = Record::new(value, 10, options)?;
+            Event::Auction(Auction {
+                id: r.number("id")?,
+                item_name: r.string("item_name")?,
+                description: r.string("description")?,
+                initial_bid: r.number("initial_bid")?,
+                reserve: r.number("reserve")?,
+                date_time: r.timestamp("date_time")?,
+                expires: r.timestamp("expires")?,
+                seller: r.number("seller")?,
+                category: r.number("category")?,
+                extra: r.string("extra")?,
+            })
+        }
+        "bid" => {
+            let r = Record::new(value, 7, options)?;
+            Event::Bid(Bid {
+                auction: r.number("auction")?,
+                bidder: r.number("bidder")?,
+                price: r.number("price")?,
+                channel: r.string("channel")?,
+                url: r.string("url")?,
+                date_time: r.timestamp("date_time")?,
+                extra: r.string("extra")?,
+            })
+        }
+        other => bail!("unknown event type {}", other),
+    };
+    Ok(event)
+}
+
+/// A JSON object with a known number of fields.
+struct Record<'a> {
+    fields: &'a Map<String, Value>,
+    options: SerializeOptions,
+}
+
+impl<'a> Record<'a> {
+    fn new(value: &'a Value, num_fields: usize, options: SerializeOptions) -> Result<Self> {
+        let fields = value
+            .as_object()
+            .ok_or_else(|| anyhow!("expected an object, got {}", value))?;
+        // the events sent with `--produce-timestamp field` have one more
+        let produce_ts = fields.get(options.field_name("produce_ts"));
+        ensure!(
+            produce_ts.map_or(true, Value::is_u64),
+            "produce_ts should be a non-negative integer"
+        );
+        ensure!(
+            fields.len() == num_fields + produce_ts.is_some() as usize,
+            "expected {} fields, got {:?}",
+            num_fields,
+            fields.keys().collect::<Vec<_>>()
+        );
+        Ok(Self { fields, options })
+    }
+
+    fn raw(&self, name: &'static str) -> Result<&'a Value> {
+        let name = self.options.field_name(name);
+        self.fields
+            .get(name)
+            .ok_or_else(|| anyhow!("missing field {}", name))
+    }
+
+    fn number(&self, name: &'static str) -> Result<usize> {
+        self.raw(name)?
+            .as_u64()
+            .map(|n| n as usize)
+            .ok_or_else(|| anyhow!("{} should be a non-negative integer", name))
+    }
+
+    fn string(&self, name: &'static str) -> Result<String> {
+        self.raw(name)?
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| anyhow!("{} should be a string", name))
+    }
+
+    fn timestamp(&self, name: &'static str) -> Result<usize> {
+        let value = self.raw(name)?;
+        let format = self.options.timestamp_format;
+        let milli_ts = match format {
+            TimestampFormat::String => value.as_str().and_then(timestamp_string_to_milli_ts),
+            TimestampFormat::Rfc3339 => value.as_str().and_then(rfc3339_to_milli_ts),
+            TimestampFormat::EpochMillis => value.as_u64().map(|ms| ms as usize),
+            TimestampFormat::EpochMicros => value
+                .as_u64()
+                .filter(|us| us % 1000 == 0)
+                .map(|us| (us / 1000) as usize),
+        };
+        milli_ts
+            .ok_or_else(|| anyhow!("{} should be a {:?} timestamp, got {}", name, format, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::test_events;
+    use crate::parser::FieldNaming;
+
+    #[test]
+    fn test_round_trip() {
+        let events = test_events(100);
+        for timestamp_format in [
+            TimestampFormat::String,
+            TimestampFormat::EpochMillis,
+            TimestampFormat::EpochMicros,
+            TimestampFormat::Rfc3339,
+        ] {
+            for field_naming in [FieldNaming::SnakeCase, FieldNaming::CamelCase] {
+                let options = SerializeOptions {
+                    timestamp_format,
+                    field_naming,
+                };
+                for event in &events {
+                    let combined = event.to_json(true, options);
+                    assert_eq!(
+                        &decode_json(combined.as_bytes(), None, options).unwrap(),
+                        event
+                    );
+                    let separate = event.to_json(false, options);
+                    let event_type = Some(event.event_type());
+                    assert_eq!(
+                        &decode_json(separate.as_bytes(), event_type, options).unwrap(),
+                        event
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_records() {
+        let options = SerializeOptions::default();
+        let bid = r#"{"auction":1000,"bidder":1001,"price":42,"channel":"Google","url":"u","date_time":"2015-07-15 00:00:00.001","extra":""}"#;
+        assert!(decode_json(bid.as_bytes(), Some("bid"), options).is_ok());
+        for invalid in [
+            // a missing, an extra and a mistyped field
+            bid.replace(r#""price":42,"#, ""),
+            bid.replace(r#""extra":"""#, r#""extra":"","more":1"#),
+            bid.replace(r#""price":42"#, r#""price":"42""#),
+            bid.replace("2015-07-15 00:00:00.001", "yesterday"),
+            bid.replace("date_time", "dateTime"),
+            bid[1..].to_string(),
+        ] {
+            assert!(decode_json(invalid.as_bytes(), Some("bid"), options).is_err());
+        }
+        assert!(decode_json(bid.as_bytes(), Some("person"), options).is_err());
+        let produced = bid.replace(r#""extra":"""#, r#""extra":"","produce_ts":42"#);
+        assert!(decode_json(produced.as_bytes(), Some("bid"), options).is_ok());
+        let produced = produced.replace("42", r#""42""#);
+        assert!(decode_json(produced.as_bytes(), Some("bid"), options).is_err());
+        let combined = format!(
+            r#"{{"event_type":2,"person":null,"auction":null,"bid":{}}}"#,
+            bid
+        );
+        assert!(decode_json(combined.as_bytes(), None, options).is_ok());
+        let mismatched = combined.replace(r#""event_type":2"#, r#""event_type":1"#);
+        assert!(decode_json(mismatched.as_bytes(), None, options).is_err());
+    }
+}
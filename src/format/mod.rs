@@ -0,0 +1,89 @@
+use anyhow::{ensure, Result};
+
+use crate::format::avro::AvroEncoder;
+use crate::format::protobuf::ProtobufEncoder;
+use crate::format::view::EventView;
+use crate::generator::nexmark::event::Event;
+use crate::parser::{EventFormat, FieldNaming, TimestampFormat};
+
+pub mod avro;
+pub mod decode;
+pub mod protobuf;
+pub mod view;
+
+/// How the fields of the events are written, shared by all the formats.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SerializeOptions {
+    pub timestamp_format: TimestampFormat,
+    pub field_naming: FieldNaming,
+}
+
+impl SerializeOptions {
+    /// Returns the name of a field of `Person`, `Auction` or `Bid` following `field_naming`.
+    /// The `event_type` of the combined layout keeps its name, as in the Flink DDL.
+    pub fn field_name(&self, name: &'static str) -> &'static str {
+        match self.field_naming {
+            FieldNaming::SnakeCase => name,
+            FieldNaming::CamelCase => match name {
+                "email_address" => "emailAddress",
+                "credit_card" => "creditCard",
+                "date_time" => "dateTime",
+                "item_name" => "itemName",
+                "initial_bid" => "initialBid",
+                "produce_ts" => "produceTs",
+                name => name,
+            },
+        }
+    }
+}
+
+/// Serializes the events into the payloads of the kafka records.
+pub enum EventEncoder {
+    /// `combined` wraps the events into the combined layout, as when all of them are sent to one
+    /// topic.
+    Json {
+        combined: bool,
+        options: SerializeOptions,
+    },
+    Avro(AvroEncoder),
+    Protobuf(ProtobufEncoder),
+}
+
+impl EventEncoder {
+    pub fn new(format: EventFormat, combined: bool, options: SerializeOptions) -> Self {
+        match format {
+            EventFormat::Json => EventEncoder::Json { combined, options },
+            EventFormat::Avro => EventEncoder::Avro(AvroEncoder::new(combined, options)),
+            EventFormat::Protobuf => {
+                EventEncoder::Protobuf(ProtobufEncoder::new(combined, options))
+            }
+        }
+    }
+
+    /// Checks that the format can write the events with the selected options.
+    pub fn check(&self) -> Result<()> {
+        if let EventEncoder::Protobuf(encoder) = self {
+            ensure!(
+                matches!(
+                    encoder.options().timestamp_format,
+                    TimestampFormat::String | TimestampFormat::Rfc3339
+                ),
+                "the protobuf format only supports string timestamps, as declared in proto/nexmark.proto"
+            );
+        }
+        Ok(())
+    }
+
+    /// Serializes an event, followed by the time it is produced at if `produce_ts` is set, which
+    /// only the json format writes.
+    pub fn encode(&self, event: Event, produce_ts: Option<u64>) -> Result<Vec<u8>> {
+        match self {
+            EventEncoder::Json { combined, options } => {
+                let view = EventView::new(&event, *combined, *options).with_produce_ts(produce_ts);
+                Ok(serde_json::to_vec(&view)?)
+            }
+            EventEncoder::Avro(encoder) => encoder.encode(event),
+            EventEncoder::Protobuf(encoder) => Ok(encoder.encode(event)),
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use crate::format::SerializeOptions;
+use crate::generator::nexmark::event::{Auction, Bid, Event, Person};
+use crate::generator::nexmark::utils::{milli_ts_to_rfc3339, milli_ts_to_timestamp_string};
+use crate::parser::TimestampFormat;
+
+/// The wire type of the `int64` fields.
+const VARINT: u64 = 0;
+/// The wire type of the `string` and message fields.
+const LEN: u64 = 2;
+
+/// Encodes the events as the messages of `proto/nexmark.proto`.
+///
+/// Like the official encoders, fields holding their default value, i.e. 0 or an empty string, are
+/// omitted. Timestamps are strings, so only the string timestamp formats are supported, see
+/// `EventEncoder::check`.
+pub struct ProtobufEncoder {
+    combined: bool,
+    options: SerializeOptions,
+}
+
+impl ProtobufEncoder {
+    pub fn new(combined: bool, options: SerializeOptions) -> Self {
+        Self { combined, options }
+    }
+
+    pub fn options(&self) -> SerializeOptions {
+        self.options
+    }
+
+    pub fn encode(&self, event: Event) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(256);
+        if self.combined {
+            let (event_type, field) = match &event {
+                Event::Person(_) => (0, 2),
+                Event::Auction(_) => (1, 3),
+                Event::Bid(_) => (2, 4),
+            };
+            write_int64(&mut buf, 1, event_type);
+            let mut message = Vec::with_capacity(256);
+            write_event(&mut message, &event, self.options);
+            write_bytes(&mut buf, field, &message);
+        } else {
+            write_event(&mut buf, &event, self.options);
+        }
+        buf
+    }
+}
+
+fn write_event(buf: &mut Vec<u8>, event: &Event, options: SerializeOptions) {
+    let ts = |milli_ts| match options.timestamp_format {
+        TimestampFormat::Rfc3339 => milli_ts_to_rfc3339(milli_ts),
+        _ => milli_ts_to_timestamp_string(milli_ts),
+    };
+    match event {
+        Event::Person(p) => write_person(buf, p, ts(p.date_time)),
+        Event::Auction(a) => write_auction(buf, a, ts(a.date_time), ts(a.expires)),
+        Event::Bid(b) => write_bid(buf, b, ts(b.date_time)),
+    }
+}
+
+fn write_person(buf: &mut Vec<u8>, p: &Person, date_time: String) {
+    write_int64(buf, 1, p.id as i64);
+    write_string(buf, 2, &p.name);
+    write_string(buf, 3, &p.email_address);
+    write_string(buf, 4, &p.credit_card);
+    write_string(buf, 5, &p.city);
+    write_string(buf, 6, &p.state);
+    write_string(buf, 7, &date_time);
+    write_string(buf, 8, &p.extra);
+}
+
+fn write_auction(buf: &mut Vec<u8>, a: &Auction, date_time: String, expires: String) {
+    write_int64(buf, 1, a.id as i64);
+    write_string(buf, 2, &a.item_name);
+    write_string(buf, 3, &a.description);
+    write_int64(buf, 4, a.initial_bid as i64);
+    write_int64(buf, 5, a.reserve as i64);
+    write_string(buf, 6, &date_time);
+    write_string(buf, 7, &expires);
+    write_int64(buf, 8, a.seller as i64);
+    write_int64(buf, 9, a.category as i64);
+    write_string(buf, 10, &a.extra);
+}
+
+fn write_bid(buf: &mut Vec<u8>, b: &Bid, date_time: String) {
+    write_int64(buf, 1, b.auction as i64);
+    write_int64(buf, 2, b.bidder as i64);
+    write_int64(buf, 3, b.price as i64);
+    write_string(buf, 4, &b.channel);
+    write_string(buf, 5, &b.url);
+    write_string(buf, 6, &date_time);
+    write_string(buf, 7, &b.extra);
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn write_int64(buf: &mut Vec<u8>, field: u64, n: i64) {
+    if n != 0 {
+        write_varint(buf, field << 3 | VARINT);
+        write_varint(buf, n as u64);
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, field: u64, s: &str) {
+    if !s.is_empty() {
+        write_bytes(buf, field, s.as_bytes());
+    }
+}
+
+fn write_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(buf, field << 3 | LEN);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::NaiveDateTime;
+
+    use super::*;
+    use crate::generator::test_events;
+
+    #[derive(Debug, PartialEq)]
+    enum Field<'a> {
+        Varint(u64),
+        Bytes(&'a [u8]),
+    }
+
+    /// Decodes the fields of a message by their number.
+    fn decode(mut buf: &[u8]) -> HashMap<u64, Field<'_>> {
+        let varint = |buf: &mut &[u8]| {
+            let (mut n, mut shift) = (0u64, 0);
+            loop {
+                let byte = buf[0];
+                *buf = &buf[1..];
+                n |= ((byte & 0x7f) as u64) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    return n;
+                }
+            }
+        };
+        let mut fields = HashMap::new();
+        while !buf.is_empty() {
+            let tag = varint(&mut buf);
+            let field = match tag & 7 {
+                VARINT => Field::Varint(varint(&mut buf)),
+                LEN => {
+                    let len = varint(&mut buf) as usize;
+                    let (bytes, rest) = buf.split_at(len);
+                    buf = rest;
+                    Field::Bytes(bytes)
+                }
+                wire_type => panic!("unexpected wire type {}", wire_type),
+            };
+            assert!(fields.insert(tag >> 3, field).is_none());
+        }
+        fields
+    }
+
+    fn int64(fields: &HashMap<u64, Field<'_>>, field: u64) -> usize {
+        match fields.get(&field) {
+            Some(Field::Varint(n)) => *n as usize,
+            None => 0,
+            other => panic!("expected an int64, got {:?}", other),
+        }
+    }
+
+    fn string(fields: &HashMap<u64, Field<'_>>, field: u64) -> String {
+        match fields.get(&field) {
+            Some(Field::Bytes(bytes)) => String::from_utf8(bytes.to_vec()).unwrap(),
+            None => String::new(),
+            other => panic!("expected a string, got {:?}", other),
+        }
+    }
+
+    fn timestamp(fields: &HashMap<u64, Field<'_>>, field: u64) -> usize {
+        NaiveDateTime::parse_from_str(&string(fields, field), "%Y-%m-%d %H:%M:%S%.f")
+            .unwrap()
+            .timestamp_millis() as usize
+    }
+
+    fn decode_event(buf: &[u8], event_type: usize) -> Event {
+        let f = decode(buf);
+        match event_type {
+            0 => Event::Person(Person {
+                id: int64(&f, 1),
+                name: string(&f, 2),
+                email_address: string(&f, 3),
+                credit_card: string(&f, 4),
+                city: string(&f, 5),
+                state: string(&f, 6),
+                date_time: timestamp(&f, 7),
+                extra: string(&f, 8),
+            }),
+            1 => Event::Auction(Auction {
+                id: int64(&f, 1),
+                item_name: string(&f, 2),
+                description: string(&f, 3),
+                initial_bid: int64(&f, 4),
+                reserve: int64(&f, 5),
+                date_time: timestamp(&f, 6),
+                expires: timestamp(&f, 7),
+                seller: int64(&f, 8),
+                category: int64(&f, 9),
+                extra: string(&f, 10),
+            }),
+            _ => Event::Bid(Bid {
+                auction: int64(&f, 1),
+                bidder: int64(&f, 2),
+                price: int64(&f, 3),
+                channel: string(&f, 4),
+                url: string(&f, 5),
+                date_time: timestamp(&f, 6),
+                extra: string(&f, 7),
+            }),
+        }
+    }
+
+    fn event_type(event: &Event) -> usize {
+        match event {
+            Event::Person(_) => 0,
+            Event::Auction(_) => 1,
+            Event::Bid(_) => 2,
+        }
+    }
+
+    #[test]
+    fn test_encode_separate() {
+        let encoder = ProtobufEncoder::new(false, SerializeOptions::default());
+        for event in test_events(50) {
+            let payload = encoder.encode(event.clone());
+            assert_eq!(decode_event(&payload, event_type(&event)), event);
+        }
+    }
+
+    #[test]
+    fn test_encode_combined() {
+        let encoder = ProtobufEncoder::new(true, SerializeOptions::default());
+        for event in test_events(50) {
+            let payload = encoder.encode(event.clone());
+            let fields = decode(&payload);
+            let event_type = int64(&fields, 1);
+            assert_eq!(event_type, self::event_type(&event));
+            assert_eq!(fields.len(), if event_type == 0 { 1 } else { 2 });
+            let Some(Field::Bytes(message)) = fields.get(&(event_type as u64 + 2)) else {
+                panic!("the message of the event is not set");
+            };
+            assert_eq!(decode_event(message, event_type), event);
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+use crate::format::SerializeOptions;
+use crate::generator::nexmark::event::{Auction, Bid, Event, Person};
+use crate::generator::nexmark::utils::{milli_ts_to_rfc3339, milli_ts_to_timestamp_string};
+use crate::parser::TimestampFormat;
+
+/// Serializes an event with the layout selected for the run, either alone or wrapped in the
+/// combined layout with its `event_type`, as when all the events are sent to one topic.
+pub struct EventView<'a> {
+    event: &'a Event,
+    combined: bool,
+    options: SerializeOptions,
+    /// Written after the fields of the person, auction or bid with `--produce-timestamp field`.
+    produce_ts: Option<u64>,
+}
+
+impl<'a> EventView<'a> {
+    pub fn new(event: &'a Event, combined: bool, options: SerializeOptions) -> Self {
+        Self {
+            event,
+            combined,
+            options,
+            produce_ts: None,
+        }
+    }
+
+    pub fn with_produce_ts(self, produce_ts: Option<u64>) -> Self {
+        Self { produce_ts, ..self }
+    }
+}
+
+impl Serialize for EventView<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let options = self.options;
+        let produce_ts = self.produce_ts;
+        if !self.combined {
+            return match self.event {
+                Event::Person(p) => {
+                    RecordView(PersonView(p, options), produce_ts).serialize(serializer)
+                }
+                Event::Auction(a) => {
+                    RecordView(AuctionView(a, options), produce_ts).serialize(serializer)
+                }
+                Event::Bid(b) => RecordView(BidView(b, options), produce_ts).serialize(serializer),
+            };
+        }
+        let mut s = serializer.serialize_struct("CombinedEvent", 4)?;
+        let (event_type, person, auction, bid) = match self.event {
+            Event::Person(p) => (
+                0,
+                Some(RecordView(PersonView(p, options), produce_ts)),
+                None,
+                None,
+            ),
+            Event::Auction(a) => (
+                1,
+                None,
+                Some(RecordView(AuctionView(a, options), produce_ts)),
+                None,
+            ),
+            Event::Bid(b) => (
+                2,
+                None,
+                None,
+                Some(RecordView(BidView(b, options), produce_ts)),
+            ),
+        };
+        s.serialize_field("event_type", &event_type)?;
+        s.serialize_field("person", &person)?;
+        s.serialize_field("auction", &auction)?;
+        s.serialize_field("bid", &bid)?;
+        s.end()
+    }
+}
+
+pub struct PersonView<'a>(pub &'a Person, pub SerializeOptions);
+
+impl Serialize for PersonView<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RecordView(self, None).serialize(serializer)
+    }
+}
+
+impl FieldsView for PersonView<'_> {
+    const NAME: &'static str = "Person";
+    const LEN: usize = 8;
+
+    fn options(&self) -> SerializeOptions {
+        self.1
+    }
+
+    fn serialize_fields<S: SerializeStruct>(&self, s: &mut S) -> Result<(), S::Error> {
+        let PersonView(p, options) = self;
+        s.serialize_field(options.field_name("id"), &p.id)?;
+        s.serialize_field(options.field_name("name"), &p.name)?;
+        s.serialize_field(options.field_name("email_address"), &p.email_address)?;
+        s.serialize_field(options.field_name("credit_card"), &p.credit_card)?;
+        s.serialize_field(options.field_name("city"), &p.city)?;
+        s.serialize_field(options.field_name("state"), &p.state)?;
+        s.serialize_field(
+            options.field_name("date_time"),
+            &TimestampView(p.date_time, options),
+        )?;
+        s.serialize_field(options.field_name("extra"), &p.extra)?;
+        Ok(())
+    }
+}
+
+pub struct AuctionView<'a>(pub &'a Auction, pub SerializeOptions);
+
+impl Serialize for AuctionView<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RecordView(self, None).serialize(serializer)
+    }
+}
+
+impl FieldsView for AuctionView<'_> {
+    const NAME: &'static str = "Auction";
+    const LEN: usize = 10;
+
+    fn options(&self) -> SerializeOptions {
+        self.1
+    }
+
+    fn serialize_fields<S: SerializeStruct>(&self, s: &mut S) -> Result<(), S::Error> {
+        let AuctionView(a, options) = self;
+        s.serialize_field(options.field_name("id"), &a.id)?;
+        s.serialize_field(options.field_name("item_name"), &a.item_name)?;
+        s.serialize_field(options.field_name("description"), &a.description)?;
+        s.serialize_field(options.field_name("initial_bid"), &a.initial_bid)?;
+        s.serialize_field(options.field_name("reserve"), &a.reserve)?;
+        s.serialize_field(
+            options.field_name("date_time"),
+            &TimestampView(a.date_time, options),
+        )?;
+        s.serialize_field(
+            options.field_name("expires"),
+            &TimestampView(a.expires, options),
+        )?;
+        s.serialize_field(options.field_name("seller"), &a.seller)?;
+        s.serialize_field(options.field_name("category"), &a.category)?;
+        s.serialize_field(options.field_name("extra"), &a.extra)?;
+        Ok(())
+    }
+}
+
+pub struct BidView<'a>(pub &'a Bid, pub SerializeOptions);
+
+impl Serialize for BidView<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RecordView(self, None).serialize(serializer)
+    }
+}
+
+impl FieldsView for BidView<'_> {
+    const NAME: &'static str = "Bid";
+    const LEN: usize = 7;
+
+    fn options(&self) -> SerializeOptions {
+        self.1
+    }
+
+    fn serialize_fields<S: SerializeStruct>(&self, s: &mut S) -> Result<(), S::Error> {
+        let BidView(b, options) = self;
+        s.serialize_field(options.field_name("auction"), &b.auction)?;
+        s.serialize_field(options.field_name("bidder"), &b.bidder)?;
+        s.serialize_field(options.field_name("price"), &b.price)?;
+        s.serialize_field(options.field_name("channel"), &b.channel)?;
+        s.serialize_field(options.field_name("url"), &b.url)?;
+        s.serialize_field(
+            options.field_name("date_time"),
+            &TimestampView(b.date_time, options),
+        )?;
+        s.serialize_field(options.field_name("extra"), &b.extra)?;
+        Ok(())
+    }
+}
+
+/// The fields of a person, auction or bid, serialized by `RecordView`.
+trait FieldsView {
+    const NAME: &'static str;
+    const LEN: usize;
+
+    fn options(&self) -> SerializeOptions;
+
+    fn serialize_fields<S: SerializeStruct>(&self, s: &mut S) -> Result<(), S::Error>;
+}
+
+impl<T: FieldsView> FieldsView for &T {
+    const NAME: &'static str = T::NAME;
+    const LEN: usize = T::LEN;
+
+    fn options(&self) -> SerializeOptions {
+        (*self).options()
+    }
+
+    fn serialize_fields<S: SerializeStruct>(&self, s: &mut S) -> Result<(), S::Error> {
+        (*self).serialize_fields(s)
+    }
+}
+
+/// A person, auction or bid, followed by the time it was produced at if any.
+struct RecordView<V>(V, Option<u64>);
+
+impl<V: FieldsView> Serialize for RecordView<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let RecordView(view, produce_ts) = self;
+        let len = V::LEN + produce_ts.is_some() as usize;
+        let mut s = serializer.serialize_struct(V::NAME, len)?;
+        view.serialize_fields(&mut s)?;
+        if let Some(produce_ts) = produce_ts {
+            s.serialize_field(view.options().field_name("produce_ts"), produce_ts)?;
+        }
+        s.end()
+    }
+}
+
+/// A millisecond timestamp, serialized following `--timestamp-format`.
+struct TimestampView<'a>(usize, &'a SerializeOptions);
+
+impl Serialize for TimestampView<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.1.timestamp_format {
+            TimestampFormat::String => {
+                serializer.serialize_str(&milli_ts_to_timestamp_string(self.0))
+            }
+            TimestampFormat::EpochMillis => serializer.serialize_u64(self.0 as u64),
+            TimestampFormat::EpochMicros => serializer.serialize_u64(self.0 as u64 * 1000),
+            TimestampFormat::Rfc3339 => serializer.serialize_str(&milli_ts_to_rfc3339(self.0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::parser::FieldNaming;
+
+    fn bid() -> Event {
+        Event::Bid(Bid {
+            auction: 1000,
+            bidder: 2001,
+            price: 42,
+            channel: "Google".to_string(),
+            url: "https://www.nexmark.com/item.htm".to_string(),
+            date_time: 1_436_918_400_001,
+            extra: String::new(),
+        })
+    }
+
+    fn to_value(event: &Event, combined: bool, timestamp_format: TimestampFormat) -> Value {
+        let options = SerializeOptions {
+            timestamp_format,
+            ..Default::default()
+        };
+        serde_json::to_value(EventView::new(event, combined, options)).unwrap()
+    }
+
+    #[test]
+    fn test_timestamp_formats() {
+        let event = bid();
+        for (format, date_time) in [
+            (TimestampFormat::String, json!("2015-07-15 00:00:00.001")),
+            (TimestampFormat::EpochMillis, json!(1_436_918_400_001u64)),
+            (
+                TimestampFormat::EpochMicros,
+                json!(1_436_918_400_001_000u64),
+            ),
+            (TimestampFormat::Rfc3339, json!("2015-07-15T00:00:00.001Z")),
+        ] {
+            assert_eq!(to_value(&event, false, format)["date_time"], date_time);
+        }
+    }
+
+    #[test]
+    fn test_camel_case() {
+        let options = SerializeOptions {
+            field_naming: FieldNaming::CamelCase,
+            ..Default::default()
+        };
+        let value = serde_json::to_value(EventView::new(&bid(), true, options)).unwrap();
+        assert_eq!(value["event_type"], json!(2));
+        assert_eq!(value["bid"]["dateTime"], json!("2015-07-15 00:00:00.001"));
+        assert_eq!(value["bid"].get("date_time"), None);
+    }
+
+    #[test]
+    fn test_combined() {
+        let value = to_value(&bid(), true, TimestampFormat::EpochMillis);
+        assert_eq!(value["event_type"], json!(2));
+        assert_eq!(value["person"], Value::Null);
+        assert_eq!(value["auction"], Value::Null);
+        assert_eq!(value["bid"]["date_time"], json!(1_436_918_400_001u64));
+    }
+
+    #[test]
+    fn test_produce_ts() {
+        let options = SerializeOptions::default();
+        let event = bid();
+        let view = EventView::new(&event, true, options).with_produce_ts(Some(42));
+        let value = serde_json::to_value(view).unwrap();
+        assert_eq!(value["bid"]["produce_ts"], json!(42));
+        assert_eq!(value["bid"].as_object().unwrap().len(), 8);
+        let value = to_value(&event, false, TimestampFormat::String);
+        assert_eq!(value.get("produce_ts"), None);
+    }
+}
@@ -1,11 +1,13 @@
 use crate::generator::nexmark::config::NexmarkConfig;
-use crate::generator::nexmark::properties::NexmarkProperties;
 
 #[derive(Debug, Clone)]
 pub struct GeneratorConfig {
     pub nexmark_config: NexmarkConfig,
     pub base_time: u64,
     pub max_events: u64,
+    /// The id of the first event, to resume an interrupted run. The events before it are
+    /// skipped, so that `max_events` still bounds the ids.
+    pub start_event: u64,
     pub generator_num: u64,
     pub skip_person: bool,
     pub skip_auction: bool,
@@ -14,13 +16,15 @@ pub struct GeneratorConfig {
 
 impl GeneratorConfig {
     pub fn new(
+        mut nexmark_config: NexmarkConfig,
         max_events: u64,
         base_time: u64,
         generator_num: u64,
         skip_event_types: String,
     ) -> Self {
-        let properties = NexmarkProperties::default();
-        let config = NexmarkConfig::from(properties).unwrap();
+        if nexmark_config.use_real_time {
+            nexmark_config.base_time = base_time as usize;
+        }
         let max_events = match max_events {
             0 => u64::MAX,
             _ => max_events,
@@ -29,17 +33,14 @@ impl GeneratorConfig {
         let skip_auction = skip_event_types.contains("auction");
         let skip_bid = skip_event_types.contains("bid");
         Self {
-            nexmark_config: config,
+            nexmark_config,
             base_time,
             generator_num,
             max_events,
+            start_event: 0,
             skip_person,
             skip_auction,
             skip_bid,
         }
     }
-
-    pub fn get_event_delay_microseconds(event_rate: usize, num_generators: usize) -> u64 {
-        1_000_000.0 as u64 * num_generators as u64 / event_rate as u64
-    }
 }
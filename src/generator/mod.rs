@@ -1,26 +1,116 @@
 use crate::generator::config::GeneratorConfig;
 use crate::generator::nexmark::event::Event;
+use crate::generator::reorder::{DisorderMeter, DisorderStats, EmitKey, ReorderBuffer};
 
 pub mod config;
 pub mod nexmark;
+pub mod reorder;
 pub mod source;
 
 pub struct NexmarkGenerator {
     config: GeneratorConfig,
     local_events_so_far: u64,
     index: u64,
+    /// Delays the events by their event time, if the config has disorder.
+    reorder: Option<ReorderBuffer>,
+    disorder: DisorderMeter,
 }
 
 impl NexmarkGenerator {
     pub fn new(config: GeneratorConfig, index: u64) -> Self {
+        let local_events_so_far =
+            Self::local_events_at(config.start_event, config.generator_num, index);
+        let reorder = config
+            .nexmark_config
+            .has_disorder()
+            .then(ReorderBuffer::default);
+        let disorder = DisorderMeter::new(index, config.nexmark_config.max_delay_ms as u64);
         Self {
             config,
-            local_events_so_far: 0,
+            local_events_so_far,
             index,
+            reorder,
+            disorder,
         }
     }
 
+    /// Returns the events a generator skips to start at `event_id`, rounded up to its own first
+    /// event id from there since the generators take turns.
+    pub fn local_events_at(event_id: u64, generator_num: u64, index: u64) -> u64 {
+        event_id.saturating_sub(index).div_ceil(generator_num)
+    }
+
+    /// Returns the events generated or skipped by this generator, up to the first one it still
+    /// delays.
+    pub fn local_events_so_far(&self) -> u64 {
+        self.reorder
+            .as_ref()
+            .and_then(ReorderBuffer::first_pending)
+            .unwrap_or(self.local_events_so_far)
+    }
+
+    /// Returns the last event emitted out of order, after which the events of a resumed
+    /// generator are emitted.
+    pub fn last_emitted(&self) -> Option<EmitKey> {
+        self.reorder.as_ref().and_then(ReorderBuffer::last_emitted)
+    }
+
+    /// Continues after the events generated or skipped by this generator in a previous run, and
+    /// the ones it emitted up to `last_emitted` if it delays them.
+    pub fn resume(&mut self, local_events_so_far: u64, last_emitted: Option<EmitKey>) {
+        self.local_events_so_far = local_events_so_far;
+        if self.reorder.is_some() {
+            self.reorder = Some(ReorderBuffer::resume(last_emitted));
+        }
+    }
+
+    /// Returns the disorder of the events emitted so far.
+    pub fn disorder_stats(&self) -> DisorderStats {
+        self.disorder.stats()
+    }
+
+    /// Changes the event types skipped by the next events.
+    pub fn set_skipped_event_types(
+        &mut self,
+        skip_person: bool,
+        skip_auction: bool,
+        skip_bid: bool,
+    ) {
+        self.config.skip_person = skip_person;
+        self.config.skip_auction = skip_auction;
+        self.config.skip_bid = skip_bid;
+    }
+
     pub fn next_event(&mut self) -> Option<Event> {
+        let event = match self.reorder {
+            Some(_) => self.next_delayed_event(),
+            None => self.next_generated_event().map(|(_, event)| event),
+        }?;
+        self.disorder.record(&event);
+        Some(event)
+    }
+
+    fn next_delayed_event(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.reorder.as_mut()?.pop(false) {
+                return Some(event);
+            }
+            let Some((local_index, event)) = self.next_generated_event() else {
+                return self.reorder.as_mut()?.pop(true);
+            };
+            let event_id = local_index * self.config.generator_num + self.index;
+            let delay = self
+                .config
+                .nexmark_config
+                .event_delay(event_id as usize, &event);
+            self.reorder
+                .as_mut()?
+                .push(local_index, event, delay as u64);
+        }
+    }
+
+    /// Returns the next event in the order of the event ids, with its local index.
+    fn next_generated_event(&mut self) -> Option<(u64, Event)> {
         loop {
             let new_event_id = self.local_events_so_far * self.config.generator_num + self.index;
             if new_event_id >= self.config.max_events {
@@ -29,15 +119,182 @@ impl NexmarkGenerator {
             if let Some((event, _)) = Event::new(
                 new_event_id as usize,
                 &self.config.nexmark_config,
-                0,
+                self.config.base_time as usize,
                 self.config.skip_person,
                 self.config.skip_auction,
                 self.config.skip_bid,
             ) {
                 self.local_events_so_far += 1;
-                return Some(event);
+                return Some((self.local_events_so_far - 1, event));
             }
             self.local_events_so_far += 1;
         }
     }
 }
+
+/// Returns the first `max_events` events of a single generator with the default properties.
+#[cfg(test)]
+pub(crate) fn test_events(max_events: u64) -> Vec<Event> {
+    use crate::generator::nexmark::config::NexmarkConfig;
+    use crate::generator::nexmark::properties::NexmarkProperties;
+
+    let nexmark_config = NexmarkConfig::from(NexmarkProperties::default()).unwrap();
+    let config = GeneratorConfig::new(nexmark_config, max_events, 0, 1, String::new());
+    let mut generator = NexmarkGenerator::new(config, 0);
+    std::iter::from_fn(|| generator.next_event()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::nexmark::config::NexmarkConfig;
+    use crate::generator::nexmark::properties::NexmarkProperties;
+
+    #[test]
+    fn test_use_real_time() {
+        let wallclock_base_time = 1_666_000_000_000;
+        let properties = NexmarkProperties {
+            use_real_time: true,
+            ..Default::default()
+        };
+        let nexmark_config = NexmarkConfig::from(properties).unwrap();
+        let config =
+            GeneratorConfig::new(nexmark_config, 10, wallclock_base_time, 1, String::new());
+        let mut generator = NexmarkGenerator::new(config, 0);
+        match generator.next_event().unwrap() {
+            Event::Person(p) => assert_eq!(p.date_time, wallclock_base_time as usize),
+            e => panic!("the first event should be a person, got {:?}", e),
+        }
+    }
+
+    /// Returns the events of every generator serialized as JSON.
+    fn generate(
+        properties: NexmarkProperties,
+        generator_num: u64,
+        start_event: u64,
+    ) -> Vec<Vec<String>> {
+        let nexmark_config = NexmarkConfig::from(properties).unwrap();
+        (0..generator_num)
+            .map(|index| {
+                let config = GeneratorConfig {
+                    start_event,
+                    ..GeneratorConfig::new(
+                        nexmark_config.clone(),
+                        1000,
+                        0,
+                        generator_num,
+                        String::new(),
+                    )
+                };
+                let mut generator = NexmarkGenerator::new(config, index);
+                std::iter::from_fn(|| generator.next_event())
+                    .map(|event| event.to_json(true, Default::default()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resume() {
+        let uninterrupted = generate(NexmarkProperties::default(), 1, 0).remove(0);
+        assert_eq!(uninterrupted.len(), 1000);
+        let resumed = generate(NexmarkProperties::default(), 1, 437).remove(0);
+        assert_eq!(resumed, uninterrupted[437..]);
+
+        // every generator resumes at its own next event
+        for (index, resumed) in generate(NexmarkProperties::default(), 3, 437)
+            .iter()
+            .enumerate()
+        {
+            let expected = uninterrupted
+                .iter()
+                .enumerate()
+                .skip(437)
+                .filter(|(event_id, _)| event_id % 3 == index)
+                .map(|(_, event)| event.clone())
+                .collect::<Vec<_>>();
+            assert_eq!(resumed, &expected);
+        }
+    }
+
+    #[test]
+    fn test_seed() {
+        let seeded = |seed| {
+            let properties = NexmarkProperties {
+                seed,
+                ..Default::default()
+            };
+            generate(properties, 1, 0).remove(0)
+        };
+        assert_eq!(seeded(Some(0)), seeded(None));
+        assert_eq!(seeded(Some(42)), seeded(Some(42)));
+        assert_ne!(seeded(Some(42)), seeded(None));
+        assert_ne!(seeded(Some(42)), seeded(Some(43)));
+        // only the content changes, not the ids and timestamps of the events
+        assert!(seeded(Some(42))[0].starts_with(r#"{"event_type":0,"person":{"id":1000,"#));
+    }
+
+    fn disorder_properties() -> NexmarkProperties {
+        NexmarkProperties {
+            max_delay_ms: Some(20),
+            bid_late_percent: Some(10.0),
+            late_delay_ms: Some(30),
+            ..Default::default()
+        }
+    }
+
+    fn new_generator(properties: NexmarkProperties) -> NexmarkGenerator {
+        let nexmark_config = NexmarkConfig::from(properties).unwrap();
+        let config = GeneratorConfig::new(nexmark_config, 1000, 0, 1, String::new());
+        NexmarkGenerator::new(config, 0)
+    }
+
+    #[test]
+    fn test_disorder() {
+        let mut generator = new_generator(NexmarkProperties::default());
+        let mut in_order: Vec<_> = std::iter::from_fn(|| generator.next_event()).collect();
+        assert_eq!(generator.disorder_stats().out_of_order, 0);
+
+        let mut generator = new_generator(disorder_properties());
+        let events: Vec<_> = std::iter::from_fn(|| generator.next_event()).collect();
+        assert_ne!(events, in_order);
+        let mut latest_time = 0;
+        for event in &events {
+            latest_time = latest_time.max(event.date_time());
+            // only the bids are delayed beyond max_delay_ms
+            let bound = match event {
+                Event::Bid(_) => 20 + 30,
+                _ => 20,
+            };
+            assert!(latest_time - event.date_time() <= bound);
+        }
+        let stats = generator.disorder_stats();
+        assert_eq!(stats.events, 1000);
+        assert!(stats.out_of_order > 0 && stats.late > 0);
+        assert!(stats.late < stats.out_of_order && stats.max_lateness_ms <= 50);
+
+        // the same events are emitted, in the same order every time
+        let mut sorted = events.clone();
+        sorted.sort_by_key(|event| event.to_json(true, Default::default()));
+        in_order.sort_by_key(|event| event.to_json(true, Default::default()));
+        assert_eq!(sorted, in_order);
+        let mut generator = new_generator(disorder_properties());
+        assert!(std::iter::from_fn(|| generator.next_event()).eq(events));
+    }
+
+    #[test]
+    fn test_resume_with_disorder() {
+        let mut generator = new_generator(disorder_properties());
+        let uninterrupted: Vec<_> = std::iter::from_fn(|| generator.next_event()).collect();
+        for emitted in [1, 100, 437, 999] {
+            let mut generator = new_generator(disorder_properties());
+            let mut events: Vec<_> = (0..emitted).map_while(|_| generator.next_event()).collect();
+            // the events still delayed are generated again, the ones emitted after them aren't
+            // emitted again
+            let mut resumed = new_generator(disorder_properties());
+            resumed.resume(generator.local_events_so_far(), generator.last_emitted());
+            events.extend(std::iter::from_fn(|| resumed.next_event()));
+            assert_eq!(events, uninterrupted);
+        }
+    }
+}
@@ -1,20 +1,53 @@
 use std::collections::HashMap;
 use std::f64::consts::PI;
+use std::fmt;
 
+use anyhow::{bail, ensure};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::generator::nexmark::event::Event;
 use crate::generator::nexmark::properties::NexmarkProperties;
-use crate::generator::nexmark::utils::{build_channel_url_map, get_base_url};
+use crate::generator::nexmark::utils::{build_channel_url_map, get_base_url, perturb_seed};
 
 pub const CHANNEL_NUMBER: usize = 10_000;
 const NEXMARK_BASE_TIME: usize = 1_436_918_400_000;
+/// Separates the random streams of the event delays from the ones of the events, whose ids
+/// never reach it.
+const DELAY_STREAM: u64 = 1 << 63;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DelayDistribution {
+    Uniform,
+    Exponential,
+}
 
-#[derive(PartialEq)]
-enum RateShape {
+impl fmt::Display for DelayDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DelayDistribution::Uniform => write!(f, "uniform"),
+            DelayDistribution::Exponential => write!(f, "exponential"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateShape {
     Square,
     Sine,
 }
 
+impl fmt::Display for RateShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateShape::Square => write!(f, "square"),
+            RateShape::Sine => write!(f, "sine"),
+        }
+    }
+}
+
 /// Nexmark Configuration
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct NexmarkConfig {
     /// Maximum number of people to consider as active for placing auctions or
     /// bids.
@@ -50,6 +83,20 @@ pub struct NexmarkConfig {
     pub first_event_number: usize,
     /// Time for first event (ms since epoch).
     pub base_time: usize,
+    /// Perturbs the seeds of the random streams, see `perturb_seed`.
+    pub seed: u64,
+    /// Replace `base_time` with the wall-clock time at which the generators start.
+    pub use_real_time: bool,
+    /// Shape of the event rate, between `first_event_rate` and `next_event_rate`.
+    pub rate_shape: RateShape,
+    /// Period of the rate shape, in seconds.
+    pub rate_period: usize,
+    /// Event rate at the start of the period, in events per `us_per_unit`.
+    pub first_event_rate: usize,
+    /// Event rate in the middle of the period, in events per `us_per_unit`.
+    pub next_event_rate: usize,
+    /// Unit of the event rates, in microseconds.
+    pub us_per_unit: usize,
     /// Delay before changing the current inter-event delay.
     pub step_length: usize,
     /// Number of events per epoch.
@@ -95,6 +142,19 @@ pub struct NexmarkConfig {
     pub person_id_lead: usize,
     /// Use to calculate inter_event_delays for rate-shape sine.
     pub sine_approx_steps: usize,
+    /// Number of rows of the side input of Query 13, keyed from 0.
+    pub side_input_rows: usize,
+    /// Bound of the event-time delays of the events emitted out of order, 0 emits them in
+    /// order.
+    pub max_delay_ms: usize,
+    /// Distribution of the delays up to `max_delay_ms`.
+    pub delay_distribution: DelayDistribution,
+    /// Mean of the exponential delays, which are truncated at `max_delay_ms`.
+    pub mean_delay_ms: usize,
+    /// Percentages of the persons, auctions and bids delayed beyond `max_delay_ms`.
+    pub late_percents: [f64; 3],
+    /// Longest delay of the late events past `max_delay_ms`.
+    pub late_delay_ms: usize,
     /// The collection of U.S. statees
     pub us_states: Vec<String>,
     /// The collection of U.S. cities.
@@ -128,6 +188,7 @@ impl NexmarkConfig {
         let hot_channel_ratio = properties.hot_channel_ratio.unwrap_or(2);
         let first_event_id = properties.hot_first_event_id.unwrap_or(0);
         let first_event_number = properties.first_event_number.unwrap_or(0);
+        let seed = properties.seed.unwrap_or(0);
         let num_categories = properties.num_categories.unwrap_or(5);
         let auction_id_lead = properties.auction_id_lead.unwrap_or(10);
         let hot_seller_ratio_2 = properties.hot_seller_ratio_2.unwrap_or(100);
@@ -142,7 +203,26 @@ impl NexmarkConfig {
         let first_category_id = properties.first_category_id.unwrap_or(10);
         let person_id_lead = properties.person_id_lead.unwrap_or(10);
         let sine_approx_steps = properties.sine_approx_steps.unwrap_or(10);
+        let side_input_rows = properties.side_input_rows.unwrap_or(10_000);
+        let max_delay_ms = properties.max_delay_ms.unwrap_or(0);
+        let delay_distribution = match properties.delay_distribution.as_deref() {
+            None | Some("uniform") => DelayDistribution::Uniform,
+            Some("exponential") => DelayDistribution::Exponential,
+            Some(other) => bail!(
+                "delay_distribution should be uniform or exponential, got {}",
+                other
+            ),
+        };
+        let mean_delay_ms = properties.mean_delay_ms.unwrap_or(max_delay_ms / 4);
+        let late_percent = properties.late_percent.unwrap_or(0.0);
+        let late_percents = [
+            properties.person_late_percent.unwrap_or(late_percent),
+            properties.auction_late_percent.unwrap_or(late_percent),
+            properties.bid_late_percent.unwrap_or(late_percent),
+        ];
+        let late_delay_ms = properties.late_delay_ms.unwrap_or(10_000);
         let base_time = properties.base_time.unwrap_or(NEXMARK_BASE_TIME);
+        let use_real_time = properties.use_real_time;
         let us_states = split_string_arg(
             properties
                 .us_states
@@ -159,11 +239,13 @@ impl NexmarkConfig {
             "shultz,abrams,spencer,white,bartels,walton,smith,jones,noris".to_string()
         }));
         let hot_channels = split_string_arg("Google,Facebook,Baidu,Apple".to_string());
-        let hot_urls = (0..4).map(get_base_url).collect();
-        let rate_shape = if properties.rate_shape.unwrap_or_else(|| "sine".to_string()) == "sine" {
-            RateShape::Sine
-        } else {
-            RateShape::Square
+        let hot_urls = (0..4)
+            .map(|i| get_base_url(perturb_seed(i, seed)))
+            .collect();
+        let rate_shape = match properties.rate_shape.as_deref().unwrap_or("sine") {
+            "sine" => RateShape::Sine,
+            "square" => RateShape::Square,
+            other => bail!("rate_shape should be sine or square, got {}", other),
         };
         let rate_period = properties.rate_period.unwrap_or(600);
         let first_rate = properties.first_event_rate.unwrap_or(10_000);
@@ -171,6 +253,63 @@ impl NexmarkConfig {
         let us_per_unit = properties.us_per_unit.unwrap_or(1_000_000); // Rate is in μs
         let generators = properties.threads.unwrap_or(1) as f32;
 
+        for (name, value) in [
+            ("active_people", active_people),
+            ("out_of_order_group_size", out_of_order_group_size),
+            ("hot_seller_ratio", hot_seller_ratio),
+            ("hot_auction_ratio", hot_auction_ratio),
+            ("hot_bidder_ratio", hot_bidder_ratio),
+            ("hot_channel_ratio", hot_channel_ratio),
+            ("num_categories", num_categories),
+            ("hot_seller_ratio_2", hot_seller_ratio_2),
+            ("hot_auction_ratio_2", hot_auction_ratio_2),
+            ("hot_bidder_ratio_2", hot_bidder_ratio_2),
+            ("person_proportion", person_proportion),
+            ("auction_proportion", auction_proportion),
+            ("sine_approx_steps", sine_approx_steps),
+            ("side_input_rows", side_input_rows),
+            ("late_delay_ms", late_delay_ms),
+            ("rate_period", rate_period),
+            ("first_event_rate", first_rate),
+            ("next_event_rate", next_rate),
+            ("us_per_unit", us_per_unit),
+            ("threads", generators as usize),
+        ] {
+            ensure!(value > 0, "{} should be greater than 0", name);
+        }
+        ensure!(
+            out_of_order_group_size == 1 || (max_delay_ms == 0 && late_percents == [0.0; 3]),
+            "out_of_order_group_size can't be combined with max_delay_ms or late percentages"
+        );
+        ensure!(
+            delay_distribution == DelayDistribution::Uniform
+                || max_delay_ms == 0
+                || mean_delay_ms > 0,
+            "mean_delay_ms should be greater than 0 with exponential delays"
+        );
+        for (name, percent) in
+            [("person", 0), ("auction", 1), ("bid", 2)].map(|(name, i)| (name, late_percents[i]))
+        {
+            ensure!(
+                (0.0..=100.0).contains(&percent),
+                "{}_late_percent should be between 0 and 100, got {}",
+                name,
+                percent
+            );
+        }
+        for (name, values) in [
+            ("us_states", &us_states),
+            ("us_cities", &us_cities),
+            ("first_names", &first_names),
+            ("last_names", &last_names),
+        ] {
+            ensure!(
+                values.iter().all(|v| !v.is_empty()),
+                "{} should be a comma separated list of non-empty values",
+                name
+            );
+        }
+
         // Calculate inter event delays array.
         let mut inter_event_delays = Vec::new();
         let rate_to_period = |r| (us_per_unit) as f32 / r as f32;
@@ -184,7 +323,7 @@ impl NexmarkConfig {
                 }
                 RateShape::Sine => {
                     let mid = (first_rate + next_rate) as f64 / 2.0;
-                    let amp = (first_rate - next_rate) as f64 / 2.0;
+                    let amp = (first_rate as f64 - next_rate as f64) / 2.0;
                     for i in 0..sine_approx_steps {
                         let r = (2.0 * PI * i as f64) / sine_approx_steps as f64;
                         let rate = mid + amp * r.cos();
@@ -211,7 +350,7 @@ impl NexmarkConfig {
             }
         }
 
-        let channel_url_map = build_channel_url_map(CHANNEL_NUMBER);
+        let channel_url_map = build_channel_url_map(CHANNEL_NUMBER, seed);
 
         Ok(NexmarkConfig {
             active_people,
@@ -227,6 +366,13 @@ impl NexmarkConfig {
             first_event_id,
             first_event_number,
             base_time,
+            seed,
+            use_real_time,
+            rate_shape,
+            rate_period,
+            first_event_rate: first_rate,
+            next_event_rate: next_rate,
+            us_per_unit,
             step_length,
             events_per_epoch,
             epoch_period,
@@ -246,6 +392,12 @@ impl NexmarkConfig {
             first_category_id,
             person_id_lead,
             sine_approx_steps,
+            side_input_rows,
+            max_delay_ms,
+            delay_distribution,
+            mean_delay_ms,
+            late_percents,
+            late_delay_ms,
             us_states,
             us_cities,
             hot_channels,
@@ -284,6 +436,50 @@ impl NexmarkConfig {
         0
     }
 
+    /// Returns the random stream of an event, which only depends on its id and the seed.
+    pub fn event_rng(&self, event_id: usize) -> SmallRng {
+        SmallRng::seed_from_u64(perturb_seed(event_id as u64, self.seed))
+    }
+
+    /// Returns the type of the event generated for an event number.
+    pub fn event_type(&self, event_number: usize) -> &'static str {
+        let rem = self.next_adjusted_event(event_number) % self.proportion_denominator;
+        if rem < self.person_proportion {
+            "person"
+        } else if rem < self.person_proportion + self.auction_proportion {
+            "auction"
+        } else {
+            "bid"
+        }
+    }
+
+    /// Returns whether the events are emitted out of order by their event time.
+    pub fn has_disorder(&self) -> bool {
+        self.max_delay_ms > 0 || self.late_percents.iter().any(|&p| p > 0.0)
+    }
+
+    /// Returns how long after its event time an event is emitted, which only depends on its id
+    /// and the seed, so that a resumed generator delays its events the same way.
+    pub fn event_delay(&self, event_id: usize, event: &Event) -> usize {
+        let mut rng =
+            SmallRng::seed_from_u64(perturb_seed(event_id as u64 | DELAY_STREAM, self.seed));
+        let late_percent = self.late_percents[match event {
+            Event::Person(_) => 0,
+            Event::Auction(_) => 1,
+            Event::Bid(_) => 2,
+        }];
+        if late_percent > 0.0 && rng.gen::<f64>() * 100.0 < late_percent {
+            return self.max_delay_ms + rng.gen_range(1..=self.late_delay_ms);
+        }
+        match self.delay_distribution {
+            DelayDistribution::Uniform => rng.gen_range(0..=self.max_delay_ms),
+            DelayDistribution::Exponential => {
+                let delay = -(self.mean_delay_ms as f64) * (1.0 - rng.gen::<f64>()).ln();
+                (delay.round() as usize).min(self.max_delay_ms)
+            }
+        }
+    }
+
     /// Returns the next adjusted event.
     pub fn next_adjusted_event(&self, events_so_far: usize) -> usize {
         let n = self.out_of_order_group_size;
@@ -292,6 +488,102 @@ impl NexmarkConfig {
     }
 }
 
+/// Prints the effective configuration, one `key = value` line per knob, so that it can be
+/// logged at startup and reused as a config file.
+impl fmt::Display for NexmarkConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scalars = [
+            ("active_people", self.active_people),
+            ("in_flight_auctions", self.in_flight_auctions),
+            ("out_of_order_group_size", self.out_of_order_group_size),
+            ("avg_person_byte_size", self.avg_person_byte_size),
+            ("avg_auction_byte_size", self.avg_auction_byte_size),
+            ("avg_bid_byte_size", self.avg_bid_byte_size),
+            ("hot_seller_ratio", self.hot_seller_ratio),
+            ("hot_auction_ratio", self.hot_auction_ratio),
+            ("hot_bidder_ratio", self.hot_bidder_ratio),
+            ("hot_channel_ratio", self.hot_channel_ratio),
+            ("hot_first_event_id", self.first_event_id),
+            ("first_event_number", self.first_event_number),
+            ("base_time", self.base_time),
+            ("num_categories", self.num_categories),
+            ("auction_id_lead", self.auction_id_lead),
+            ("hot_seller_ratio_2", self.hot_seller_ratio_2),
+            ("hot_auction_ratio_2", self.hot_auction_ratio_2),
+            ("hot_bidder_ratio_2", self.hot_bidder_ratio_2),
+            ("person_proportion", self.person_proportion),
+            ("auction_proportion", self.auction_proportion),
+            ("bid_proportion", self.bid_proportion),
+            ("first_auction_id", self.first_auction_id),
+            ("first_person_id", self.first_person_id),
+            ("first_category_id", self.first_category_id),
+            ("person_id_lead", self.person_id_lead),
+            ("sine_approx_steps", self.sine_approx_steps),
+            ("side_input_rows", self.side_input_rows),
+            ("max_delay_ms", self.max_delay_ms),
+            ("mean_delay_ms", self.mean_delay_ms),
+            ("late_delay_ms", self.late_delay_ms),
+            ("rate_period", self.rate_period),
+            ("first_event_rate", self.first_event_rate),
+            ("next_event_rate", self.next_event_rate),
+            ("us_per_unit", self.us_per_unit),
+            ("threads", self.num_event_generators),
+        ];
+        for (name, value) in scalars {
+            writeln!(f, "{} = {}", name, value)?;
+        }
+        let lists = [
+            ("us_states", &self.us_states),
+            ("us_cities", &self.us_cities),
+            ("first_names", &self.first_names),
+            ("last_names", &self.last_names),
+        ];
+        for (name, values) in lists {
+            writeln!(f, "{} = {:?}", name, values)?;
+        }
+        writeln!(f, "rate_shape = \"{}\"", self.rate_shape)?;
+        writeln!(f, "delay_distribution = \"{}\"", self.delay_distribution)?;
+        for (name, percent) in ["person", "auction", "bid"].iter().zip(self.late_percents) {
+            writeln!(f, "{}_late_percent = {}", name, percent)?;
+        }
+        writeln!(f, "seed = {}", self.seed)?;
+        writeln!(f, "use_real_time = {}", self.use_real_time)?;
+        writeln!(f, "# derived from the rate properties")?;
+        writeln!(f, "# inter_event_delays = {:?}", self.inter_event_delays)?;
+        write!(f, "# step_length = {}", self.step_length)
+    }
+}
+
 fn split_string_arg(string: String) -> Vec<String> {
     string.split(',').map(String::from).collect::<Vec<String>>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::nexmark::properties::flatten_config;
+
+    #[test]
+    fn test_display_round_trip() {
+        let properties = NexmarkProperties {
+            active_people: Some(500),
+            seed: Some(7),
+            rate_shape: Some("square".to_string()),
+            rate_period: Some(60),
+            first_event_rate: Some(1000),
+            next_event_rate: Some(5000),
+            us_per_unit: Some(1000),
+            late_percent: Some(2.5),
+            max_delay_ms: Some(100),
+            delay_distribution: Some("exponential".to_string()),
+            us_states: Some("ca,wa".to_string()),
+            threads: Some(2),
+            ..Default::default()
+        };
+        let config = NexmarkConfig::from(properties).unwrap();
+        // only the file layer, without the env vars of the shell running the tests
+        let file_layer = flatten_config(toml::from_str(&config.to_string()).unwrap()).unwrap();
+        let reloaded = NexmarkProperties::from_layers([file_layer]).unwrap();
+        assert_eq!(NexmarkConfig::from(reloaded).unwrap(), config);
+    }
+}
@@ -2,53 +2,16 @@ use std::cmp::{max, min};
 
 use rand::rngs::SmallRng;
 use rand::seq::SliceRandom;
-use rand::{Rng, SeedableRng};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
+use crate::format::view::EventView;
+use crate::format::SerializeOptions;
 use crate::generator::nexmark::config::{NexmarkConfig, CHANNEL_NUMBER};
-use crate::generator::nexmark::utils::{milli_ts_to_timestamp_string, NexmarkRng};
+use crate::generator::nexmark::utils::NexmarkRng;
 
 type Id = usize;
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
-pub struct CombinedEvent {
-    event_type: u64,
-    /// The Person event
-    person: Option<Person>,
-    /// The Auction event.
-    auction: Option<Auction>,
-    /// The Bid event.
-    bid: Option<Bid>,
-}
-
-impl CombinedEvent {
-    fn new(
-        event_type: u64,
-        person: Option<Person>,
-        auction: Option<Auction>,
-        bid: Option<Bid>,
-    ) -> Self {
-        Self {
-            event_type,
-            person,
-            auction,
-            bid,
-        }
-    }
-
-    pub fn person(person: Person) -> Self {
-        Self::new(0, Some(person), None, None)
-    }
-
-    pub fn auction(auction: Auction) -> Self {
-        Self::new(1, None, Some(auction), None)
-    }
-
-    pub fn bid(bid: Bid) -> Self {
-        Self::new(2, None, None, Some(bid))
-    }
-}
-
 /// The `Nexmark` Event, including `Person`, `Auction`, and `Bid`.
 #[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -71,18 +34,15 @@ impl Event {
         skip_auction: bool,
         skip_bid: bool,
     ) -> Option<(Event, usize)> {
-        let rem = nex.next_adjusted_event(events_so_far) % nex.proportion_denominator;
-        if rem < nex.person_proportion {
-            if skip_person {
-                return None;
-            }
-        } else if rem < nex.person_proportion + nex.auction_proportion {
-            if skip_auction {
-                return None;
-            }
-        } else if skip_bid {
-            return None;
+        let skip = match nex.event_type(events_so_far) {
+            "person" => skip_person,
+            "auction" => skip_auction,
+            _ => skip_bid,
         };
+        if skip {
+            return None;
+        }
+        let rem = nex.next_adjusted_event(events_so_far) % nex.proportion_denominator;
         Some(Self::inner_new(
             rem,
             events_so_far,
@@ -110,22 +70,39 @@ impl Event {
         (event, new_wall_clock_base_time)
     }
 
-    pub fn to_json(self, combine_event: bool) -> String {
-        if combine_event {
-            let combined_event = match self {
-                Event::Person(p) => CombinedEvent::person(p),
-                Event::Auction(a) => CombinedEvent::auction(a),
-                Event::Bid(b) => CombinedEvent::bid(b),
-            };
-            serde_json::to_string(&combined_event).unwrap()
-        } else {
-            match self {
-                Event::Person(p) => serde_json::to_string(&p).unwrap(),
-                Event::Auction(a) => serde_json::to_string(&a).unwrap(),
-                Event::Bid(b) => serde_json::to_string(&b).unwrap(),
-            }
+    /// The name of the event type, as used in the metrics and file names.
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            Event::Person(_) => "person",
+            Event::Auction(_) => "auction",
+            Event::Bid(_) => "bid",
         }
     }
+
+    /// The id of the entity the event is about: the person or auction itself, or the auction of
+    /// a bid.
+    pub fn entity_id(&self) -> Id {
+        match self {
+            Event::Person(person) => person.id,
+            Event::Auction(auction) => auction.id,
+            Event::Bid(bid) => bid.auction,
+        }
+    }
+
+    /// Returns the event time of the event, in ms since epoch.
+    pub fn date_time(&self) -> usize {
+        match self {
+            Event::Person(person) => person.date_time,
+            Event::Auction(auction) => auction.date_time,
+            Event::Bid(bid) => bid.date_time,
+        }
+    }
+
+    /// Serializes the event as JSON, wrapped in the combined layout with its `event_type` if
+    /// `combine_event` is set.
+    pub fn to_json(&self, combine_event: bool, options: SerializeOptions) -> String {
+        serde_json::to_string(&EventView::new(self, combine_event, options)).unwrap()
+    }
 }
 
 /// Person represents a person submitting an item for auction and/or making a
@@ -145,7 +122,7 @@ pub struct Person {
     /// One of several US states as a two-letter string.
     pub state: String,
     /// A millisecond timestamp for the event origin.
-    pub date_time: String,
+    pub date_time: usize,
     /// Extra information
     pub extra: String,
 }
@@ -153,7 +130,7 @@ pub struct Person {
 impl Person {
     /// Creates a new `Person` event.
     fn new(event_id: usize, time: usize, nex: &NexmarkConfig) -> Self {
-        let rng = &mut SmallRng::seed_from_u64(event_id as u64);
+        let rng = &mut nex.event_rng(event_id);
         let id = Self::last_id(event_id, nex) + nex.first_person_id;
         let name = format!(
             "{} {}",
@@ -179,7 +156,7 @@ impl Person {
             credit_card,
             city,
             state,
-            date_time: milli_ts_to_timestamp_string(time),
+            date_time: time,
             extra,
         }
     }
@@ -214,9 +191,9 @@ pub struct Auction {
     /// The minimum price for the auction to succeed.
     pub reserve: usize,
     /// A millisecond timestamp for the event origin.
-    pub date_time: String,
-    /// A UNIX epoch timestamp for the expiration date of the auction.
-    pub expires: String,
+    pub date_time: usize,
+    /// A millisecond timestamp for the expiration date of the auction.
+    pub expires: usize,
     /// The ID of the person that created this auction.
     pub seller: Id,
     /// The ID of the category this auction belongs to.
@@ -227,16 +204,15 @@ pub struct Auction {
 
 impl Auction {
     fn new(events_so_far: usize, event_id: usize, time: usize, nex: &NexmarkConfig) -> Self {
-        let rng = &mut SmallRng::seed_from_u64(event_id as u64);
+        let rng = &mut nex.event_rng(event_id);
         let id = Self::last_id(event_id, nex) + nex.first_auction_id;
         let item_name = rng.gen_string(20);
         let description = rng.gen_string(100);
         let initial_bid = rng.gen_price();
 
         let reserve = initial_bid + rng.gen_price();
-        let date_time = milli_ts_to_timestamp_string(time);
-        let expires =
-            milli_ts_to_timestamp_string(time + Self::next_length(events_so_far, rng, time, nex));
+        let date_time = time;
+        let expires = time + Self::next_length(events_so_far, rng, time, nex);
         let mut seller = if rng.gen_range(0..nex.hot_seller_ratio) > 0 {
             (Person::last_id(event_id, nex) / nex.hot_seller_ratio_2) * nex.hot_seller_ratio_2
         } else {
@@ -316,14 +292,14 @@ pub struct Bid {
     /// The url of this bid
     pub url: String,
     /// A millisecond timestamp for the event origin.
-    pub date_time: String,
+    pub date_time: usize,
     /// Extra information
     pub extra: String,
 }
 
 impl Bid {
     fn new(event_id: usize, time: usize, nex: &NexmarkConfig) -> Self {
-        let rng = &mut SmallRng::seed_from_u64(event_id as u64);
+        let rng = &mut nex.event_rng(event_id);
         let auction = if 0 < rng.gen_range(0..nex.hot_auction_ratio) {
             (Auction::last_id(event_id, nex) / nex.hot_auction_ratio_2) * nex.hot_auction_ratio_2
         } else {
@@ -354,7 +330,7 @@ impl Bid {
             auction: auction + nex.first_auction_id,
             bidder: bidder + nex.first_person_id,
             price,
-            date_time: milli_ts_to_timestamp_string(time),
+            date_time: time,
             channel,
             url,
             extra,
@@ -1,4 +1,5 @@
 pub mod config;
 pub mod event;
 pub mod properties;
+pub mod side_input;
 pub mod utils;
@@ -1,10 +1,70 @@
-use serde::Deserialize;
-
-// TODO(Kexiang): make them configurable
-#[derive(Clone, Debug, Default, Deserialize)]
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Prefix of the environment variables overriding nexmark properties, e.g.
+/// `NEXMARK_ACTIVE_PEOPLE=2000`.
+const ENV_PREFIX: &str = "NEXMARK_";
+/// The fields of `NexmarkProperties`, the only keys taken from the environment variables.
+const PROPERTY_NAMES: &[&str] = &[
+    "use_real_time",
+    "min_event_gap_in_ns",
+    "active_people",
+    "in_flight_auctions",
+    "out_of_order_group_size",
+    "avg_person_byte_size",
+    "avg_auction_byte_size",
+    "avg_bid_byte_size",
+    "hot_seller_ratio",
+    "hot_auction_ratio",
+    "hot_bidder_ratio",
+    "hot_channel_ratio",
+    "hot_first_event_id",
+    "first_event_number",
+    "seed",
+    "num_categories",
+    "auction_id_lead",
+    "hot_seller_ratio_2",
+    "hot_auction_ratio_2",
+    "hot_bidder_ratio_2",
+    "person_proportion",
+    "auction_proportion",
+    "bid_proportion",
+    "first_auction_id",
+    "first_person_id",
+    "first_category_id",
+    "person_id_lead",
+    "sine_approx_steps",
+    "side_input_rows",
+    "max_delay_ms",
+    "delay_distribution",
+    "mean_delay_ms",
+    "late_percent",
+    "person_late_percent",
+    "auction_late_percent",
+    "bid_late_percent",
+    "late_delay_ms",
+    "base_time",
+    "us_states",
+    "us_cities",
+    "first_names",
+    "last_names",
+    "rate_shape",
+    "rate_period",
+    "first_event_rate",
+    "events_per_sec",
+    "next_event_rate",
+    "us_per_unit",
+    "threads",
+];
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct NexmarkProperties {
-    /// The event time gap will be like the time gap in the generated data, default false
-    /// TODO(Kexiang): not supported for now
+    /// Anchors the event timestamps to the wall-clock time at which the generators start instead
+    /// of `base_time`, so that they follow the time gap in the generated data, default false
     pub use_real_time: bool,
 
     /// Minimal gap between two events, default 100000, so that the default max throughput is 10000
@@ -34,6 +94,10 @@ pub struct NexmarkProperties {
 
     pub first_event_number: Option<usize>,
 
+    /// Perturbs the random streams of all the events, default 0, the streams of the original
+    /// Nexmark generator
+    pub seed: Option<u64>,
+
     pub num_categories: Option<usize>,
 
     pub auction_id_lead: Option<usize>,
@@ -60,6 +124,33 @@ pub struct NexmarkProperties {
 
     pub sine_approx_steps: Option<usize>,
 
+    /// Rows of the side input joined by Query 13 on `mod(auction, 10000)`, default 10000
+    pub side_input_rows: Option<usize>,
+
+    /// Bound of the event-time delays of the events emitted out of order, default 0, in order
+    pub max_delay_ms: Option<usize>,
+
+    /// Distribution of the delays up to `max_delay_ms`, uniform or exponential, default uniform
+    pub delay_distribution: Option<String>,
+
+    /// Mean of the exponential delays, default a quarter of `max_delay_ms`
+    pub mean_delay_ms: Option<usize>,
+
+    /// Percentage of the events delayed beyond `max_delay_ms`, default 0
+    pub late_percent: Option<f64>,
+
+    /// Overrides `late_percent` for the persons
+    pub person_late_percent: Option<f64>,
+
+    /// Overrides `late_percent` for the auctions
+    pub auction_late_percent: Option<f64>,
+
+    /// Overrides `late_percent` for the bids
+    pub bid_late_percent: Option<f64>,
+
+    /// Longest delay of the late events past `max_delay_ms`, default 10000
+    pub late_delay_ms: Option<usize>,
+
     pub base_time: Option<usize>,
 
     pub us_states: Option<String>,
@@ -84,3 +175,175 @@ pub struct NexmarkProperties {
 
     pub threads: Option<usize>,
 }
+
+impl NexmarkProperties {
+    /// Loads the properties from three layers, each one overriding the previous:
+    /// 1. the TOML or YAML `config_file`, if any;
+    /// 2. the `NEXMARK_*` environment variables;
+    /// 3. the `key=value` pairs passed through `--property`.
+    ///
+    /// Properties that are set in none of them keep the defaults of `NexmarkConfig`.
+    pub fn load(config_file: Option<&Path>, overrides: &[String]) -> Result<Self> {
+        let file_layer = match config_file {
+            Some(path) => read_config_file(path)?,
+            None => BTreeMap::new(),
+        };
+        let env_layer = env_layer(std::env::vars());
+        let cli_layer = overrides
+            .iter()
+            .map(|kv| parse_key_value(kv))
+            .collect::<Result<_>>()?;
+        Self::from_layers([file_layer, env_layer, cli_layer])
+    }
+
+    pub(super) fn from_layers(
+        layers: impl IntoIterator<Item = BTreeMap<String, String>>,
+    ) -> Result<Self> {
+        let mut merged = BTreeMap::new();
+        for layer in layers {
+            merged.extend(layer);
+        }
+        envy::from_iter(merged).map_err(|e| anyhow!("invalid nexmark properties: {}", e))
+    }
+}
+
+/// Takes the environment variables named after a property, leaving out the other `NEXMARK_*` ones
+/// that may be set for other tools, while unknown keys in the other layers stay errors.
+fn env_layer(vars: impl IntoIterator<Item = (String, String)>) -> BTreeMap<String, String> {
+    vars.into_iter()
+        .filter_map(|(key, value)| {
+            let key = key.strip_prefix(ENV_PREFIX)?.to_lowercase();
+            PROPERTY_NAMES
+                .contains(&key.as_str())
+                .then_some((key, value))
+        })
+        .collect()
+}
+
+fn parse_key_value(kv: &str) -> Result<(String, String)> {
+    match kv.split_once('=') {
+        Some((key, value)) => Ok((key.trim().to_lowercase(), value.trim().to_string())),
+        None => bail!("property {:?} is not in the form of key=value", kv),
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<BTreeMap<String, String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+    let value: serde_json::Value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&content)?,
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content)?,
+        _ => bail!(
+            "config file {} should end with .toml, .yaml or .yml",
+            path.display()
+        ),
+    };
+    flatten_config(value).with_context(|| format!("invalid config file {}", path.display()))
+}
+
+/// Turns the top-level table of a config file into string pairs, so that all the layers
+/// can be parsed the same way as environment variables. Lists such as `us_states` are
+/// joined with commas.
+pub(super) fn flatten_config(value: serde_json::Value) -> Result<BTreeMap<String, String>> {
+    let table = match value {
+        serde_json::Value::Object(table) => table,
+        serde_json::Value::Null => return Ok(BTreeMap::new()),
+        _ => bail!("expected a table of properties"),
+    };
+    let scalar_to_string = |key: &str, value: serde_json::Value| match value {
+        serde_json::Value::String(s) => Ok(s),
+        serde_json::Value::Number(n) => Ok(n.to_string()),
+        serde_json::Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(anyhow!(
+            "property {} should be a scalar or a list of scalars",
+            key
+        )),
+    };
+    let mut properties = BTreeMap::new();
+    for (key, value) in table {
+        let value = match value {
+            serde_json::Value::Null => continue,
+            serde_json::Value::Array(values) => values
+                .into_iter()
+                .map(|v| scalar_to_string(&key, v))
+                .collect::<Result<Vec<_>>>()?
+                .join(","),
+            value => scalar_to_string(&key, value)?,
+        };
+        properties.insert(key.to_lowercase(), value);
+    }
+    Ok(properties)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_later_layers_override() {
+        let file = flatten_config(
+            toml::from_str(
+                r#"
+                active_people = 500
+                hot_seller_ratio = 8
+                us_states = ["ca", "wa"]
+                use_real_time = true
+                "#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let env = layer(&[("hot_seller_ratio", "16"), ("rate_shape", "square")]);
+        let cli = layer(&[("rate_shape", "sine")]);
+        let properties = NexmarkProperties::from_layers([file, env, cli]).unwrap();
+        assert_eq!(properties.active_people, Some(500));
+        assert_eq!(properties.hot_seller_ratio, Some(16));
+        assert_eq!(properties.us_states.as_deref(), Some("ca,wa"));
+        assert_eq!(properties.rate_shape.as_deref(), Some("sine"));
+        assert!(properties.use_real_time);
+        assert_eq!(properties.bid_proportion, None);
+    }
+
+    #[test]
+    fn test_property_names() {
+        let properties = serde_json::to_value(NexmarkProperties::default()).unwrap();
+        let mut names: Vec<_> = properties.as_object().unwrap().keys().collect();
+        let mut expected = PROPERTY_NAMES.to_vec();
+        names.sort();
+        expected.sort();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn test_env_layer() {
+        let vars = [
+            ("NEXMARK_ACTIVE_PEOPLE", "2000"),
+            ("NEXMARK_RATE_SHAPE", "sine"),
+            ("NEXMARK_PB_PATH", "/tmp/nexmark.pb"),
+            ("ACTIVE_PEOPLE", "10"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        assert_eq!(
+            env_layer(vars),
+            layer(&[("active_people", "2000"), ("rate_shape", "sine")])
+        );
+    }
+
+    #[test]
+    fn test_invalid_properties() {
+        assert!(NexmarkProperties::from_layers([layer(&[("no_such_key", "1")])]).is_err());
+        assert!(NexmarkProperties::from_layers([layer(&[("active_people", "many")])]).is_err());
+        assert!(parse_key_value("active_people").is_err());
+        assert_eq!(
+            parse_key_value("Active_People = 10").unwrap(),
+            ("active_people".to_string(), "10".to_string())
+        );
+    }
+}
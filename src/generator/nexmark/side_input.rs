@@ -0,0 +1,62 @@
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+use serde::Serialize;
+
+use crate::generator::nexmark::config::NexmarkConfig;
+use crate::generator::nexmark::utils::{perturb_seed, NexmarkRng};
+
+/// Seeds the random stream of the side input, apart from the ones of the events which are
+/// seeded by their ids.
+const SIDE_INPUT_SEED: u64 = u64::MAX;
+const MAX_VALUE_LENGTH: usize = 20;
+
+/// A row of the bounded side input table of Query 13, which the bids join on
+/// `mod(auction, 10000) = key`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SideInputRow {
+    pub key: u64,
+    pub value: String,
+}
+
+impl SideInputRow {
+    /// Generates the `side_input_rows` rows, keyed from 0, whose values only depend on the seed.
+    pub fn generate(nex: &NexmarkConfig) -> Vec<Self> {
+        let mut rng = SmallRng::seed_from_u64(perturb_seed(SIDE_INPUT_SEED, nex.seed));
+        (0..nex.side_input_rows as u64)
+            .map(|key| Self {
+                key,
+                value: rng.gen_string(MAX_VALUE_LENGTH),
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::nexmark::properties::NexmarkProperties;
+
+    #[test]
+    fn test_generate() {
+        let generate = |seed| {
+            SideInputRow::generate(
+                &NexmarkConfig::from(NexmarkProperties {
+                    seed,
+                    side_input_rows: Some(100),
+                    ..Default::default()
+                })
+                .unwrap(),
+            )
+        };
+        let rows = generate(None);
+        assert_eq!(rows.len(), 100);
+        assert!(rows.iter().enumerate().all(|(i, row)| row.key == i as u64));
+        assert_eq!(rows, generate(None));
+        assert_ne!(rows, generate(Some(42)));
+        assert!(rows[0].to_json().starts_with(r#"{"key":0,"value":""#));
+    }
+}
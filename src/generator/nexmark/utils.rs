@@ -19,6 +19,9 @@ use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
 
 const MIN_STRING_LENGTH: usize = 3;
+/// Spreads the global seed over the high bits, so that perturbed seeds don't collide with the
+/// small seeds of the unperturbed streams.
+const SEED_MULTIPLIER: u64 = 0x9e37_79b9_7f4a_7c15;
 
 pub trait NexmarkRng {
     fn gen_string(&mut self, max: usize) -> String;
@@ -100,6 +103,35 @@ pub fn milli_ts_to_timestamp_string(milli_ts: usize) -> String {
     .to_string()
 }
 
+/// Formats a millisecond timestamp as RFC 3339 in UTC, e.g. `2015-07-15T00:00:00.001Z`.
+pub fn milli_ts_to_rfc3339(milli_ts: usize) -> String {
+    NaiveDateTime::from_timestamp_opt(
+        milli_ts as i64 / 1000,
+        (milli_ts % (1000_usize)) as u32 * 1000000,
+    )
+    .unwrap()
+    .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+    .to_string()
+}
+
+/// Parses a timestamp written by `milli_ts_to_timestamp_string`.
+pub fn timestamp_string_to_milli_ts(timestamp: &str) -> Option<usize> {
+    let date_time = NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S%.f").ok()?;
+    usize::try_from(date_time.timestamp_millis()).ok()
+}
+
+/// Parses a timestamp written by `milli_ts_to_rfc3339`.
+pub fn rfc3339_to_milli_ts(timestamp: &str) -> Option<usize> {
+    let date_time = NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%.fZ").ok()?;
+    usize::try_from(date_time.timestamp_millis()).ok()
+}
+
+/// Derives the seed of a random stream, e.g. the one of an event id, from the global `--seed`.
+/// The global seed 0 leaves the streams unchanged.
+pub fn perturb_seed(stream_seed: u64, global_seed: u64) -> u64 {
+    stream_seed ^ global_seed.wrapping_mul(SEED_MULTIPLIER)
+}
+
 pub fn get_base_url(seed: u64) -> String {
     let mut rng = SmallRng::seed_from_u64(seed);
     let id0 = rng.gen_string_with_delimiter(5, "_");
@@ -111,12 +143,16 @@ pub fn get_base_url(seed: u64) -> String {
     )
 }
 
-pub fn build_channel_url_map(channel_number: usize) -> HashMap<usize, (String, String)> {
+pub fn build_channel_url_map(
+    channel_number: usize,
+    global_seed: u64,
+) -> HashMap<usize, (String, String)> {
     let mut ans = HashMap::new();
     ans.reserve(channel_number);
     for i in 0..channel_number {
-        let mut url = get_base_url(i as u64);
-        let mut rng = SmallRng::seed_from_u64(i as u64);
+        let seed = perturb_seed(i as u64, global_seed);
+        let mut url = get_base_url(seed);
+        let mut rng = SmallRng::seed_from_u64(seed);
         if rng.gen_range(0..10) > 0 {
             url.push_str("&channel_id=");
             url.push_str(&i64::abs((i as i32).reverse_bits() as i64).to_string());
@@ -155,8 +191,9 @@ mod tests {
         assert_eq!(url3, url4);
         assert_ne!(url3, url1);
 
-        let map0 = build_channel_url_map(100);
-        let map1 = build_channel_url_map(100);
+        let map0 = build_channel_url_map(100, 0);
+        let map1 = build_channel_url_map(100, 0);
         assert_eq!(map0, map1);
+        assert_ne!(map0, build_channel_url_map(100, 1));
     }
 }
@@ -0,0 +1,197 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeSet, BinaryHeap};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::generator::nexmark::event::Event;
+use crate::metrics;
+
+/// Orders the events a generator emits out of order: by the time they are due, then by the
+/// order they were generated in.
+///
+/// As the event times of a generator never decrease, an event is generated after all the events
+/// emitted so far are due, so the keys of the emitted events only increase. The events emitted
+/// before a checkpoint are then exactly the ones with a key up to the last emitted one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EmitKey {
+    /// The event time plus the delay of the event, in ms since epoch.
+    pub emit_time: u64,
+    /// The events generated or skipped by the generator before this one.
+    pub local_index: u64,
+}
+
+struct Delayed {
+    key: EmitKey,
+    event: Event,
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// Holds back the events of a generator until the event time of the generated events reaches
+/// their emit time.
+#[derive(Default)]
+pub struct ReorderBuffer {
+    delayed: BinaryHeap<Reverse<Delayed>>,
+    /// The local indexes of the delayed events, the lowest one is where a resumed generator
+    /// restarts.
+    pending: BTreeSet<u64>,
+    /// The latest event time generated so far.
+    frontier: u64,
+    /// The events up to it were emitted before, and are dropped when generated again.
+    last_emitted: Option<EmitKey>,
+}
+
+impl ReorderBuffer {
+    /// Continues after the events emitted in a previous run, up to `last_emitted`.
+    pub fn resume(last_emitted: Option<EmitKey>) -> Self {
+        Self {
+            last_emitted,
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, local_index: u64, event: Event, delay_ms: u64) {
+        let event_time = event.date_time() as u64;
+        self.frontier = self.frontier.max(event_time);
+        let key = EmitKey {
+            emit_time: event_time + delay_ms,
+            local_index,
+        };
+        if self.last_emitted.is_some_and(|last| key <= last) {
+            return;
+        }
+        self.pending.insert(local_index);
+        self.delayed.push(Reverse(Delayed { key, event }));
+    }
+
+    /// Returns the next event that is due, or the next one at all if `drain` is set because no
+    /// more events are generated.
+    pub fn pop(&mut self, drain: bool) -> Option<Event> {
+        let Reverse(next) = self.delayed.peek()?;
+        if !drain && next.key.emit_time > self.frontier {
+            return None;
+        }
+        let Reverse(Delayed { key, event }) = self.delayed.pop().unwrap();
+        self.pending.remove(&key.local_index);
+        self.last_emitted = Some(key);
+        Some(event)
+    }
+
+    /// Returns the lowest local index of the delayed events, if any.
+    pub fn first_pending(&self) -> Option<u64> {
+        self.pending.first().copied()
+    }
+
+    pub fn last_emitted(&self) -> Option<EmitKey> {
+        self.last_emitted
+    }
+}
+
+/// The disorder of the events emitted by generators, measured against the latest event time
+/// emitted before each event as a watermark would.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DisorderStats {
+    pub events: u64,
+    /// Events emitted after an event with a later event time.
+    pub out_of_order: u64,
+    /// Events emitted more than `max_delay_ms` behind the latest event time, which a watermark
+    /// bounded by `max_delay_ms` drops.
+    pub late: u64,
+    pub max_lateness_ms: u64,
+}
+
+impl DisorderStats {
+    pub fn merge(&mut self, other: &DisorderStats) {
+        self.events += other.events;
+        self.out_of_order += other.out_of_order;
+        self.late += other.late;
+        self.max_lateness_ms = self.max_lateness_ms.max(other.max_lateness_ms);
+    }
+}
+
+impl fmt::Display for DisorderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} events out of order, {} of them beyond max_delay_ms, the latest by {} ms",
+            self.out_of_order, self.events, self.late, self.max_lateness_ms
+        )
+    }
+}
+
+/// Measures the disorder of a stream of events, e.g. the ones of a generator as they are emitted.
+pub struct DisorderMeter {
+    /// Labels the metrics of the disorder of a generator.
+    generator: Option<String>,
+    max_delay_ms: u64,
+    latest_time: u64,
+    stats: DisorderStats,
+}
+
+impl DisorderMeter {
+    pub fn new(generator_idx: u64, max_delay_ms: u64) -> Self {
+        Self {
+            generator: Some(generator_idx.to_string()),
+            ..Self::unlabeled(max_delay_ms)
+        }
+    }
+
+    /// Measures the disorder without exporting it as metrics.
+    pub fn unlabeled(max_delay_ms: u64) -> Self {
+        Self {
+            generator: None,
+            max_delay_ms,
+            latest_time: 0,
+            stats: DisorderStats::default(),
+        }
+    }
+
+    pub fn record(&mut self, event: &Event) {
+        let event_time = event.date_time() as u64;
+        self.stats.events += 1;
+        if event_time >= self.latest_time {
+            self.latest_time = event_time;
+            return;
+        }
+        let lateness = self.latest_time - event_time;
+        let late = lateness > self.max_delay_ms;
+        self.stats.out_of_order += 1;
+        self.stats.late += late as u64;
+        self.stats.max_lateness_ms = self.stats.max_lateness_ms.max(lateness);
+        if let Some(generator) = &self.generator {
+            let labels = [generator.as_str(), event.event_type()];
+            metrics::EVENTS_OUT_OF_ORDER
+                .with_label_values(&labels)
+                .inc();
+            if late {
+                metrics::EVENTS_LATE.with_label_values(&labels).inc();
+            }
+            metrics::MAX_EVENT_LATENESS
+                .with_label_values(&[generator])
+                .set(self.stats.max_lateness_ms as i64);
+        }
+    }
+
+    pub fn stats(&self) -> DisorderStats {
+        self.stats
+    }
+}
@@ -1,26 +1,50 @@
 use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use async_trait::async_trait;
 use dotenv::dotenv;
-use log::info;
-use rdkafka::admin::{AdminClient, AdminOptions, NewTopic};
+use log::{error, info};
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
 use rdkafka::config::FromClientConfig;
 use rdkafka::consumer::{BaseConsumer, Consumer, DefaultConsumerContext};
+use rdkafka::producer::{FutureProducer, FutureRecord};
 use rdkafka::{ClientConfig, ClientContext};
 use serde::Deserialize;
 
-use crate::parser::ServerConfig;
+use crate::format::avro::{SchemaRegistry, SchemaSubjects};
+use crate::format::EventEncoder;
+use crate::generator::nexmark::event::Event;
+use crate::generator::nexmark::side_input::SideInputRow;
+use crate::metrics;
+use crate::parser::{Durability, Partitioning, ServerConfig};
 use crate::producer::KafkaProducer;
+use crate::sink::{DestinationCount, DestinationCounters, Sink, SinkCounters, SinkMetrics};
 
-// send one message per topic without replication
-const REPLICATION_FACTOR: i32 = 1;
 const KAFKA_GET_METADATA_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long the side input waits for room in the queue of its producer.
+const SIDE_INPUT_QUEUE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Prefixes of the env vars passed through to librdkafka, e.g. `KAFKA_PRODUCER_LINGER_MS=5`
+/// sets `linger.ms` of the producers, see `env_properties`.
+const PRODUCER_ENV_PREFIX: &str = "KAFKA_PRODUCER_";
+const ADMIN_ENV_PREFIX: &str = "KAFKA_ADMIN_";
+const CONSUMER_ENV_PREFIX: &str = "KAFKA_CONSUMER_";
+/// Prefix of the env vars setting the configs of the topics created by `--create-topic`, e.g.
+/// `KAFKA_TOPIC_RETENTION_MS=-1`.
+const TOPIC_ENV_PREFIX: &str = "KAFKA_TOPIC_";
 
 pub struct NexmarkSource {
-    producers: Vec<KafkaProducer>,
-    client_config: ClientConfig,
+    producers: Vec<Arc<KafkaProducer>>,
+    /// The config of the producers, without the `transactional.id` of every generator.
+    producer_config: ClientConfig,
+    /// The config of the admin client and of the consumer checking the topics.
+    admin_config: ClientConfig,
+    topic_configs: Vec<(String, String)>,
     env_config: Arc<EnvConfig>,
+    side_input: bool,
+    encoder: Arc<EventEncoder>,
+    counters: SinkCounters,
+    destinations: Arc<DestinationCounters>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -32,6 +56,108 @@ pub struct EnvConfig {
     pub auction_topic: String,
     pub bid_topic: String,
     pub person_topic: String,
+    /// Required by the avro format to register the schemas.
+    pub schema_registry_url: Option<String>,
+    /// The replication factor of the topics created by `--create-topic`.
+    #[serde(default = "default_replication_factor")]
+    pub replication_factor: i32,
+    /// The topic of the side input of Query 13, written with `--side-input`.
+    #[serde(default = "default_side_input_topic")]
+    pub side_input_topic: String,
+}
+
+impl EnvConfig {
+    /// The topics the events are sent to.
+    pub fn topics(&self) -> Vec<&str> {
+        if self.separate_topics {
+            vec![&self.person_topic, &self.auction_topic, &self.bid_topic]
+        } else {
+            vec![&self.base_topic]
+        }
+    }
+}
+
+fn default_replication_factor() -> i32 {
+    1
+}
+
+fn default_side_input_topic() -> String {
+    "nexmark-side-input".to_string()
+}
+
+/// How the producers, the admin client and the consumer authenticate to secured clusters, read
+/// from the `KAFKA_SECURITY_PROTOCOL`, `KAFKA_SASL_*` and `KAFKA_SSL_*` env vars.
+#[derive(Clone, Default, Deserialize)]
+pub struct KafkaSecurity {
+    /// Inferred from the other settings if not set.
+    security_protocol: Option<String>,
+    /// `PLAIN`, `SCRAM-SHA-256` or `SCRAM-SHA-512`.
+    sasl_mechanism: Option<String>,
+    sasl_username: Option<String>,
+    sasl_password: Option<String>,
+    /// The CA certificate verifying the brokers, the system ones if not set.
+    ssl_ca_location: Option<String>,
+    /// The client certificate and key, for the brokers requiring TLS client authentication.
+    ssl_certificate_location: Option<String>,
+    ssl_key_location: Option<String>,
+    ssl_key_password: Option<String>,
+}
+
+impl KafkaSecurity {
+    fn load() -> Result<Self> {
+        envy::prefixed("KAFKA_")
+            .from_env()
+            .context("invalid kafka security settings")
+    }
+
+    fn uses_ssl(&self) -> bool {
+        self.ssl_ca_location.is_some()
+            || self.ssl_certificate_location.is_some()
+            || self.ssl_key_location.is_some()
+    }
+
+    fn security_protocol(&self) -> Option<&str> {
+        match (
+            &self.security_protocol,
+            self.sasl_mechanism.is_some(),
+            self.uses_ssl(),
+        ) {
+            (Some(protocol), _, _) => Some(protocol.as_str()),
+            (None, true, true) => Some("sasl_ssl"),
+            (None, true, false) => Some("sasl_plaintext"),
+            (None, false, true) => Some("ssl"),
+            (None, false, false) => None,
+        }
+    }
+
+    /// Returns the librdkafka properties of the clients.
+    fn properties(&self) -> Result<Vec<(&'static str, &str)>> {
+        ensure!(
+            self.sasl_mechanism.is_none()
+                || (self.sasl_username.is_some() && self.sasl_password.is_some()),
+            "KAFKA_SASL_USERNAME and KAFKA_SASL_PASSWORD are required by SASL"
+        );
+        ensure!(
+            self.ssl_certificate_location.is_some() == self.ssl_key_location.is_some(),
+            "KAFKA_SSL_CERTIFICATE_LOCATION and KAFKA_SSL_KEY_LOCATION should be set together"
+        );
+        Ok([
+            ("security.protocol", self.security_protocol()),
+            ("sasl.mechanisms", self.sasl_mechanism.as_deref()),
+            ("sasl.username", self.sasl_username.as_deref()),
+            ("sasl.password", self.sasl_password.as_deref()),
+            ("ssl.ca.location", self.ssl_ca_location.as_deref()),
+            (
+                "ssl.certificate.location",
+                self.ssl_certificate_location.as_deref(),
+            ),
+            ("ssl.key.location", self.ssl_key_location.as_deref()),
+            ("ssl.key.password", self.ssl_key_password.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect())
+    }
 }
 
 impl NexmarkSource {
@@ -39,38 +165,81 @@ impl NexmarkSource {
         dotenv().ok();
         let env_config = Arc::new(NexmarkSource::load_env());
         info!("Kafka address: {:?}", env_config.kafka_host);
-        let client_config = NexmarkSource::generate_client_config(&env_config.kafka_host);
-        let producers: Vec<KafkaProducer> = (0..nexmark_config.num_event_generators)
+        let mut producer_config =
+            NexmarkSource::generate_client_config(&env_config.kafka_host, nexmark_config);
+        let mut admin_config = ClientConfig::new();
+        admin_config.set("bootstrap.servers", &env_config.kafka_host);
+        let security = KafkaSecurity::load().unwrap_or_else(|err| panic!("{:#}", err));
+        for (config, prefix) in [
+            (&mut producer_config, PRODUCER_ENV_PREFIX),
+            (&mut admin_config, ADMIN_ENV_PREFIX),
+        ] {
+            set_client_properties(config, &security, prefix)
+                .unwrap_or_else(|err| panic!("{:#}", err));
+        }
+        let encoder = Arc::new(EventEncoder::new(
+            nexmark_config.format,
+            !env_config.separate_topics,
+            nexmark_config.serialize_options(),
+        ));
+        let destinations = Arc::new(DestinationCounters::default());
+        // the partitions that receive no events are reported too
+        for topic in env_config.topics() {
+            for partition in 0..env_config.num_partitions {
+                destinations.add(topic, partition, 0);
+            }
+        }
+        let producers = (0..nexmark_config.num_event_generators)
             .map(|i| {
-                KafkaProducer::new(
-                    &client_config,
+                Arc::new(KafkaProducer::new(
+                    &producer_config,
                     Arc::clone(&env_config),
+                    Arc::clone(&encoder),
+                    Arc::clone(&destinations),
                     i,
-                    nexmark_config.num_event_generators,
-                )
+                    nexmark_config,
+                ))
             })
             .collect();
         Self {
             producers,
-            client_config,
+            producer_config,
+            admin_config,
+            topic_configs: env_properties(std::env::vars(), TOPIC_ENV_PREFIX),
             env_config,
+            side_input: nexmark_config.side_input,
+            encoder,
+            counters: SinkCounters::default(),
+            destinations,
         }
     }
 
-    fn generate_client_config(kafka_host: &str) -> ClientConfig {
+    fn generate_client_config(kafka_host: &str, config: &ServerConfig) -> ClientConfig {
         let mut client_config = ClientConfig::new();
         client_config
             .set("bootstrap.servers", kafka_host)
             .set("batch.size", "100000")
-            .set("linger.ms", "0")
+            // the bulk mode trades latency for fuller batches
+            .set("linger.ms", if config.bulk { "100" } else { "0" })
             .set("compression.type", "lz4")
-            .set("acks", "0")
-            .set("queue.buffering.max.kbytes", "1000000")
-            .set("retries", "0");
+            .set("queue.buffering.max.kbytes", "1000000");
+        match config.durability {
+            Durability::Fast => {
+                client_config.set("acks", "0").set("retries", "0");
+            }
+            // implies acks=all and retries without duplicates or reordering
+            Durability::Idempotent | Durability::Transactional => {
+                client_config.set("enable.idempotence", "true");
+            }
+        }
+        if config.partitioning == Partitioning::ByKey {
+            // the partitioner of the Java clients, which the consumers may rely on to join
+            client_config.set("partitioner", "murmur2_random");
+        }
         client_config
     }
 
-    fn load_env() -> EnvConfig {
+    pub(crate) fn load_env() -> EnvConfig {
         dotenv().ok();
         match envy::from_env::<EnvConfig>() {
             Ok(config) => config,
@@ -80,66 +249,61 @@ impl NexmarkSource {
 
     async fn delete_topics<T: ClientContext>(&self, admin_client: &AdminClient<T>) {
         info!("Cleaning up...");
+        let mut topics = vec![
+            self.env_config.person_topic.as_str(),
+            self.env_config.auction_topic.as_str(),
+            self.env_config.bid_topic.as_str(),
+            self.env_config.base_topic.as_str(),
+        ];
+        if self.side_input {
+            topics.push(&self.env_config.side_input_topic);
+        }
         admin_client
-            .delete_topics(
-                &[
-                    self.env_config.person_topic.as_str(),
-                    self.env_config.auction_topic.as_str(),
-                    self.env_config.bid_topic.as_str(),
-                    self.env_config.base_topic.as_str(),
-                ],
-                &AdminOptions::new(),
-            )
+            .delete_topics(&topics, &AdminOptions::new())
             .await
             .unwrap();
         tokio::time::sleep(Duration::from_secs(3)).await;
     }
 
+    fn new_topic<'a>(&'a self, name: &'a str, num_partitions: i32) -> NewTopic<'a> {
+        let topic = NewTopic::new(
+            name,
+            num_partitions,
+            TopicReplication::Fixed(self.env_config.replication_factor),
+        );
+        self.topic_configs
+            .iter()
+            .fold(topic, |topic, (key, value)| topic.set(key, value))
+    }
+
     pub async fn create_topic(&self) {
-        let admin_client = AdminClient::from_config(&self.client_config).unwrap();
+        let admin_client = AdminClient::from_config(&self.admin_config).unwrap();
         self.delete_topics(&admin_client).await;
         info!("Creating...");
-        match self.env_config.separate_topics {
-            true => admin_client
-                .create_topics(
-                    vec![
-                        &NewTopic::new(
-                            &self.env_config.person_topic,
-                            self.env_config.num_partitions,
-                            rdkafka::admin::TopicReplication::Fixed(REPLICATION_FACTOR),
-                        ),
-                        &NewTopic::new(
-                            &self.env_config.auction_topic,
-                            self.env_config.num_partitions,
-                            rdkafka::admin::TopicReplication::Fixed(REPLICATION_FACTOR),
-                        ),
-                        &NewTopic::new(
-                            &self.env_config.bid_topic,
-                            self.env_config.num_partitions,
-                            rdkafka::admin::TopicReplication::Fixed(REPLICATION_FACTOR),
-                        ),
-                    ],
-                    &AdminOptions::new(),
-                )
-                .await
-                .unwrap(),
-            false => admin_client
-                .create_topics(
-                    vec![&NewTopic::new(
-                        &self.env_config.base_topic,
-                        self.env_config.num_partitions,
-                        rdkafka::admin::TopicReplication::Fixed(REPLICATION_FACTOR),
-                    )],
-                    &AdminOptions::new(),
-                )
-                .await
-                .unwrap(),
-        };
+        let mut topics: Vec<_> = self
+            .env_config
+            .topics()
+            .into_iter()
+            .map(|topic| self.new_topic(topic, self.env_config.num_partitions))
+            .collect();
+        if self.side_input {
+            // the side input is a small table read as a whole
+            topics.push(self.new_topic(&self.env_config.side_input_topic, 1));
+        }
+        let results = admin_client
+            .create_topics(&topics, &AdminOptions::new())
+            .await
+            .unwrap();
+        for result in results {
+            if let Err((topic, code)) = result {
+                error!("Failed to create topic {}: {}", topic, code);
+            }
+        }
     }
 
     pub async fn check_topic_exist(&self) -> Result<()> {
         let consumer: BaseConsumer = self
-            .client_config
+            .admin_config
             .create_with_context(DefaultConsumerContext)
             .unwrap();
 
@@ -156,17 +320,308 @@ impl NexmarkSource {
             Ok(())
         };
 
-        if self.env_config.separate_topics {
-            check(self.env_config.person_topic.as_str())?;
-            check(self.env_config.auction_topic.as_str())?;
-            check(self.env_config.bid_topic.as_str())?;
-        } else {
-            check(self.env_config.base_topic.as_str())?;
+        self.env_config.topics().into_iter().try_for_each(check)?;
+        if self.side_input {
+            check(&self.env_config.side_input_topic)?;
+        }
+        Ok(())
+    }
+
+    async fn register_schemas(&self) -> Result<()> {
+        if let EventEncoder::Avro(encoder) = self.encoder.as_ref() {
+            let url = self
+                .env_config
+                .schema_registry_url
+                .as_deref()
+                .context("SCHEMA_REGISTRY_URL is required by the avro format")?;
+            let subjects = if self.env_config.separate_topics {
+                SchemaSubjects::Separate {
+                    person: &self.env_config.person_topic,
+                    auction: &self.env_config.auction_topic,
+                    bid: &self.env_config.bid_topic,
+                }
+            } else {
+                SchemaSubjects::Combined(&self.env_config.base_topic)
+            };
+            encoder
+                .register(&SchemaRegistry::new(url), subjects)
+                .await?;
         }
         Ok(())
     }
 
-    pub fn get_producer_for_generator(&self, generator_num: usize) -> &KafkaProducer {
+    pub fn get_producer_for_generator(&self, generator_num: usize) -> &Arc<KafkaProducer> {
         &self.producers[generator_num]
     }
 }
+
+/// Runs a transaction call of a producer on the blocking threads, since librdkafka blocks until
+/// the brokers answer.
+async fn transaction_call(
+    producer: &Arc<KafkaProducer>,
+    call: fn(&KafkaProducer) -> Result<()>,
+) -> Result<()> {
+    let producer = Arc::clone(producer);
+    tokio::task::spawn_blocking(move || call(&producer)).await?
+}
+
+#[async_trait]
+impl Sink for NexmarkSource {
+    async fn open(&self) -> Result<()> {
+        self.encoder.check()?;
+        self.check_topic_exist().await?;
+        self.register_schemas().await?;
+        for producer in self.producers.iter().filter(|p| p.is_transactional()) {
+            transaction_call(producer, KafkaProducer::init_transactions)
+                .await
+                .context("failed to initialize the transactions")?;
+        }
+        Ok(())
+    }
+
+    async fn send_side_input(&self, rows: &[SideInputRow]) -> Result<()> {
+        let producer: FutureProducer = self.producer_config.create()?;
+        let topic = &self.env_config.side_input_topic;
+        let sends = rows.iter().map(|row| {
+            let producer = &producer;
+            async move {
+                let key = row.key.to_string();
+                let payload = row.to_json();
+                let record = FutureRecord::to(topic).key(&key).payload(&payload);
+                producer.send(record, SIDE_INPUT_QUEUE_TIMEOUT).await
+            }
+        });
+        let results = futures::future::join_all(sends).await;
+        let failures: Vec<_> = results.into_iter().filter_map(Result::err).collect();
+        if let Some((err, _)) = failures.first() {
+            bail!(
+                "{} of the {} side input rows failed to be delivered: {}",
+                failures.len(),
+                rows.len(),
+                err
+            );
+        }
+        Ok(())
+    }
+
+    async fn send_batch(&self, generator_idx: usize, events: Vec<Event>) -> Result<()> {
+        let producer = self.get_producer_for_generator(generator_idx);
+        if producer.is_transactional() {
+            if let Err(err) = transaction_call(producer, KafkaProducer::begin_transaction).await {
+                for event in &events {
+                    self.counters
+                        .record_error(generator_idx, event.event_type());
+                }
+                return Err(err.context("failed to begin a transaction"));
+            }
+        }
+        let mut result = Ok(());
+        let mut sent_types = vec![];
+        for event in events {
+            let topic = producer.choose_topic(&event);
+            let key = producer.choose_key(&event);
+            let partition = producer.choose_partition(&event);
+            let event_type = event.event_type();
+            let produce_ts = producer.produce_ts();
+            let sent = match producer.serialize_event(event, produce_ts) {
+                Ok(payload) => producer
+                    .send_data_to_topic(&payload, topic, key.as_deref(), partition, produce_ts)
+                    .await
+                    .map(|_| payload.len()),
+                Err(err) => Err(err),
+            };
+            match sent {
+                Ok(bytes) => {
+                    self.counters.record_sent(generator_idx, event_type, bytes);
+                    sent_types.push(event_type);
+                }
+                Err(err) => {
+                    self.counters.record_error(generator_idx, event_type);
+                    if result.is_ok() {
+                        result = Err(err.context(format!("Error in sending event to {}", topic)));
+                    }
+                }
+            }
+        }
+        // a transaction per batch, committing blocks until its events are delivered
+        if producer.is_transactional() {
+            if let Err(err) = transaction_call(producer, KafkaProducer::commit_transaction).await {
+                for event_type in sent_types {
+                    self.counters.record_error(generator_idx, event_type);
+                }
+                if result.is_ok() {
+                    result = Err(err.context("failed to commit a transaction"));
+                }
+            }
+        }
+        metrics::PRODUCER_QUEUE_DEPTH
+            .with_label_values(&[&generator_idx.to_string()])
+            .set(producer.producer.in_flight_count() as i64);
+        result
+    }
+
+    fn flush(&self, generator_idx: usize, timeout: Duration) -> Result<()> {
+        let producer = &self.get_producer_for_generator(generator_idx).producer;
+        producer.flush(timeout);
+        match producer.in_flight_count() {
+            0 => {}
+            n => bail!("{} messages still in flight after {:?}", n, timeout),
+        }
+        match producer.client().context().take_failures() {
+            0 => Ok(()),
+            n => Err(anyhow!(
+                "{} messages failed to be delivered since the last flush",
+                n
+            )),
+        }
+    }
+
+    async fn close(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn metrics(&self) -> SinkMetrics {
+        SinkMetrics {
+            events_delivered: self.destinations.snapshot().iter().map(|d| d.events).sum(),
+            ..self.counters.snapshot()
+        }
+    }
+
+    fn destinations(&self) -> Vec<DestinationCount> {
+        self.destinations.snapshot()
+    }
+}
+
+/// Returns the config of the consumer reading the topics back, see `nexmark-server verify` and
+/// `nexmark-server latency`.
+pub fn consumer_config(env_config: &EnvConfig) -> Result<ClientConfig> {
+    let mut config = ClientConfig::new();
+    config
+        .set("bootstrap.servers", &env_config.kafka_host)
+        .set("group.id", "nexmark-verify")
+        .set("enable.auto.commit", "false");
+    set_client_properties(&mut config, &KafkaSecurity::load()?, CONSUMER_ENV_PREFIX)?;
+    Ok(config)
+}
+
+/// Sets the security settings of a client, then the properties passed through the env vars with
+/// `prefix`, which win over them.
+fn set_client_properties(
+    config: &mut ClientConfig,
+    security: &KafkaSecurity,
+    prefix: &str,
+) -> Result<()> {
+    for (key, value) in security.properties()? {
+        config.set(key, value);
+    }
+    for (key, value) in env_properties(std::env::vars(), prefix) {
+        config.set(&key, &value);
+    }
+    Ok(())
+}
+
+/// Returns the librdkafka properties set by the env vars starting with `prefix`. The rest of the
+/// name is lowercased and its `_` replaced by `.`, except `__` that stands for `_`, e.g.
+/// `KAFKA_PRODUCER_MESSAGE_MAX_BYTES` sets `message.max.bytes`.
+fn env_properties(
+    vars: impl Iterator<Item = (String, String)>,
+    prefix: &str,
+) -> Vec<(String, String)> {
+    vars.filter_map(|(key, value)| {
+        let key = key.strip_prefix(prefix)?;
+        let key = key
+            .to_lowercase()
+            .split("__")
+            .map(|part| part.replace('_', "."))
+            .collect::<Vec<_>>()
+            .join("_");
+        Some((key, value))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_properties() {
+        let vars = [
+            ("KAFKA_PRODUCER_LINGER_MS", "5"),
+            ("KAFKA_PRODUCER_SSL_ENGINE__ID", "pkcs11"),
+            ("KAFKA_TOPIC_RETENTION_MS", "-1"),
+            ("KAFKA_HOST", "localhost:9092"),
+        ]
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+        assert_eq!(
+            env_properties(vars.clone().into_iter(), PRODUCER_ENV_PREFIX),
+            [
+                ("linger.ms".to_string(), "5".to_string()),
+                ("ssl.engine_id".to_string(), "pkcs11".to_string())
+            ]
+        );
+        assert_eq!(
+            env_properties(vars.into_iter(), TOPIC_ENV_PREFIX),
+            [("retention.ms".to_string(), "-1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_kafka_security() {
+        let scram = KafkaSecurity {
+            sasl_mechanism: Some("SCRAM-SHA-512".into()),
+            sasl_username: Some("nexmark".into()),
+            sasl_password: Some("nexmark-secret".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            scram.properties().unwrap(),
+            [
+                ("security.protocol", "sasl_plaintext"),
+                ("sasl.mechanisms", "SCRAM-SHA-512"),
+                ("sasl.username", "nexmark"),
+                ("sasl.password", "nexmark-secret")
+            ]
+        );
+        let mtls = KafkaSecurity {
+            ssl_ca_location: Some("ca.crt".into()),
+            ssl_certificate_location: Some("client.crt".into()),
+            ssl_key_location: Some("client.key".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            mtls.properties().unwrap(),
+            [
+                ("security.protocol", "ssl"),
+                ("ssl.ca.location", "ca.crt"),
+                ("ssl.certificate.location", "client.crt"),
+                ("ssl.key.location", "client.key")
+            ]
+        );
+
+        // librdkafka is built with the SSL and SASL support
+        let scram_ssl = KafkaSecurity {
+            security_protocol: Some("sasl_ssl".into()),
+            ..scram.clone()
+        };
+        let mut config = ClientConfig::new();
+        for (key, value) in scram_ssl.properties().unwrap() {
+            config.set(key, value);
+        }
+        config
+            .set("bootstrap.servers", "127.0.0.1:1")
+            .create::<BaseConsumer>()
+            .unwrap();
+
+        let missing_password = KafkaSecurity {
+            sasl_password: None,
+            ..scram
+        };
+        assert!(missing_password.properties().is_err());
+        let missing_key = KafkaSecurity {
+            ssl_key_location: None,
+            ..mtls
+        };
+        assert!(missing_key.properties().is_err());
+    }
+}
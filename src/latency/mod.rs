@@ -0,0 +1,284 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{bail, ensure, Context, Result};
+use log::info;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::message::{BorrowedMessage, Headers};
+use rdkafka::{Message, Offset, TopicPartitionList};
+use serde_json::Value;
+
+use crate::generator::nexmark::utils::{rfc3339_to_milli_ts, timestamp_string_to_milli_ts};
+use crate::generator::source::{consumer_config, NexmarkSource};
+use crate::parser::LatencyConfig;
+use crate::producer::PRODUCE_TS_HEADER;
+use crate::GeneratorControl;
+
+const KAFKA_GET_METADATA_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The field holding the time an event was sent at, with either `--field-naming`.
+const PRODUCE_TS_FIELDS: [&str; 2] = ["produce_ts", "produceTs"];
+/// The latencies below it are counted exactly.
+const EXACT_LATENCY_MS: u64 = 128;
+/// The higher latencies are counted in buckets of 1/2^PRECISION_BITS of their value.
+const PRECISION_BITS: u32 = 6;
+
+/// Counts latencies in ms with a bounded relative error, so that the percentiles of a long run
+/// take little memory: exactly below 128 ms, then in buckets of at most 1/64 of their value.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    /// The number of latencies by the lower bound of their bucket.
+    buckets: BTreeMap<u64, u64>,
+    count: u64,
+    max: u64,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency_ms: u64) {
+        *self.buckets.entry(bucket_start(latency_ms)).or_default() += 1;
+        self.count += 1;
+        self.max = self.max.max(latency_ms);
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (&start, &count) in &other.buckets {
+            *self.buckets.entry(start).or_default() += count;
+        }
+        self.count += other.count;
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the latency that the `quantile` of the latencies are at most, rounded up to the end
+    /// of its bucket, or `None` if there are none.
+    pub fn percentile(&self, quantile: f64) -> Option<u64> {
+        let rank = ((quantile * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (&start, &count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                return Some(bucket_end(start).min(self.max));
+            }
+        }
+        None
+    }
+}
+
+fn bucket_shift(latency_ms: u64) -> u32 {
+    if latency_ms < EXACT_LATENCY_MS {
+        return 0;
+    }
+    63 - latency_ms.leading_zeros() - PRECISION_BITS
+}
+
+fn bucket_start(latency_ms: u64) -> u64 {
+    let shift = bucket_shift(latency_ms);
+    latency_ms >> shift << shift
+}
+
+fn bucket_end(start: u64) -> u64 {
+    start + (1 << bucket_shift(start)) - 1
+}
+
+impl fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.count == 0 {
+            return write!(f, "0 records");
+        }
+        let percentile = |quantile| self.percentile(quantile).unwrap_or_default();
+        write!(
+            f,
+            "{} records, p50 {} ms, p99 {} ms, p999 {} ms, max {} ms",
+            self.count,
+            percentile(0.5),
+            percentile(0.99),
+            percentile(0.999),
+            self.max
+        )
+    }
+}
+
+/// The latencies of all the records read by `nexmark-server latency`.
+#[derive(Debug, Default)]
+pub struct LatencyReport {
+    pub latencies: LatencyHistogram,
+    /// Records without a produce timestamp, e.g. because the query drops the field.
+    pub without_timestamp: u64,
+}
+
+impl fmt::Display for LatencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Latency of all the records: {}", self.latencies)?;
+        if self.without_timestamp > 0 {
+            writeln!(
+                f,
+                "{} records without a produce timestamp were left out",
+                self.without_timestamp
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the records of a topic until `--duration-secs` or Ctrl-C, and prints the percentiles of
+/// their latency every `--report-interval-secs`.
+///
+/// The latency of a record is from the produce timestamp of the event it comes from, in a header
+/// or a field, to the timestamp of the record, which kafka sets to when it was sent or appended to
+/// the topic. The records read late, e.g. with `--from-beginning`, keep their latency.
+pub async fn run(config: LatencyConfig, control: Arc<GeneratorControl>) -> Result<LatencyReport> {
+    ensure!(
+        config.report_interval_secs > 0,
+        "--report-interval-secs should be greater than 0"
+    );
+    // the consumer blocks while polling
+    tokio::task::spawn_blocking(move || {
+        let consumer: BaseConsumer = consumer_config(&NexmarkSource::load_env())?
+            .create()
+            .context("failed to create kafka consumer")?;
+        let offset = match config.from_beginning {
+            true => Offset::Beginning,
+            false => Offset::End,
+        };
+        assign_partitions(&consumer, &config.topic, offset)?;
+        let report_interval = Duration::from_secs(config.report_interval_secs);
+        let duration =
+            (config.duration_secs > 0).then(|| Duration::from_secs(config.duration_secs));
+        let start = Instant::now();
+        let mut last_report = start;
+        let mut report = LatencyReport::default();
+        let mut interval = LatencyHistogram::default();
+        while control.is_running() && duration.map_or(true, |d| start.elapsed() < d) {
+            if let Some(message) = consumer.poll(POLL_INTERVAL) {
+                let message = message.context("failed to read a record")?;
+                match produce_ts(&message) {
+                    Some(produce_ts) => {
+                        interval.record(record_ts(&message).saturating_sub(produce_ts))
+                    }
+                    None => report.without_timestamp += 1,
+                }
+            }
+            if last_report.elapsed() >= report_interval {
+                println!("[{:>5}s] {}", start.elapsed().as_secs(), interval);
+                report.latencies.merge(&interval);
+                interval = LatencyHistogram::default();
+                last_report = Instant::now();
+            }
+        }
+        report.latencies.merge(&interval);
+        Ok(report)
+    })
+    .await?
+}
+
+fn assign_partitions(consumer: &BaseConsumer, topic: &str, offset: Offset) -> Result<()> {
+    let metadata = consumer
+        .fetch_metadata(Some(topic), KAFKA_GET_METADATA_TIMEOUT)
+        .with_context(|| format!("failed to fetch the metadata of {}", topic))?;
+    let partitions = match metadata.topics() {
+        [meta] if !meta.partitions().is_empty() => meta.partitions(),
+        _ => bail!("topic {} not found", topic),
+    };
+    let mut assignment = TopicPartitionList::new();
+    for partition in partitions {
+        assignment.add_partition_offset(topic, partition.id(), offset);
+    }
+    info!("Reading {} partitions of {}", partitions.len(), topic);
+    consumer.assign(&assignment)?;
+    Ok(())
+}
+
+/// Returns the time the event of a record was sent at, from its `produce_ts` header or field.
+fn produce_ts(message: &BorrowedMessage) -> Option<u64> {
+    if let Some(headers) = message.headers() {
+        for i in 0..headers.count() {
+            if let Some((PRODUCE_TS_HEADER, value)) = headers.get(i) {
+                return std::str::from_utf8(value).ok()?.parse().ok();
+            }
+        }
+    }
+    payload_produce_ts(message.payload()?)
+}
+
+/// Finds the `produce_ts` field of a JSON record at the top level, or in one of its objects as in
+/// the combined layout. It may have been cast to a timestamp by the system under test.
+fn payload_produce_ts(payload: &[u8]) -> Option<u64> {
+    let value: Value = serde_json::from_slice(payload).ok()?;
+    let record = value.as_object()?;
+    let nested = record.values().filter_map(Value::as_object);
+    let field = std::iter::once(record)
+        .chain(nested)
+        .find_map(|object| PRODUCE_TS_FIELDS.iter().find_map(|name| object.get(*name)))?;
+    match field {
+        Value::Number(ms) => ms.as_u64(),
+        Value::String(timestamp) => timestamp
+            .parse()
+            .ok()
+            .or_else(|| timestamp_string_to_milli_ts(timestamp).map(|ms| ms as u64))
+            .or_else(|| rfc3339_to_milli_ts(timestamp).map(|ms| ms as u64)),
+        _ => None,
+    }
+}
+
+/// Returns the timestamp of a record, or the current time if it has none.
+fn record_ts(message: &BorrowedMessage) -> u64 {
+    match message.timestamp().to_millis() {
+        Some(ms) if ms >= 0 => ms as u64,
+        _ => SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.percentile(0.5), None);
+        for latency in 1..=1000 {
+            histogram.record(latency);
+        }
+        assert_eq!(histogram.percentile(0.1), Some(100));
+        // within 1/64 above the exact percentiles
+        let p50 = histogram.percentile(0.5).unwrap();
+        assert!((500..=500 + 500 / 64).contains(&p50), "{}", p50);
+        let p99 = histogram.percentile(0.99).unwrap();
+        assert!((990..=990 + 990 / 64).contains(&p99), "{}", p99);
+        assert_eq!(histogram.percentile(1.0), Some(1000));
+
+        let mut merged = LatencyHistogram::default();
+        merged.record(1_000_000);
+        merged.merge(&histogram);
+        assert_eq!(merged.count(), 1001);
+        assert_eq!(merged.percentile(1.0), Some(1_000_000));
+        assert_eq!(merged.percentile(0.5), histogram.percentile(0.5));
+    }
+
+    #[test]
+    fn test_payload_produce_ts() {
+        let combined =
+            r#"{"event_type":2,"person":null,"auction":null,"bid":{"auction":1,"produce_ts":42}}"#;
+        assert_eq!(payload_produce_ts(combined.as_bytes()), Some(42));
+        let result = r#"{"auction":1,"produceTs":"2015-07-15 00:00:00.001"}"#;
+        assert_eq!(
+            payload_produce_ts(result.as_bytes()),
+            Some(1_436_918_400_001)
+        );
+        let result = r#"{"auction":1,"produce_ts":"2015-07-15T00:00:00.001Z"}"#;
+        assert_eq!(
+            payload_produce_ts(result.as_bytes()),
+            Some(1_436_918_400_001)
+        );
+        assert_eq!(payload_produce_ts(br#"{"auction":1}"#), None);
+        assert_eq!(payload_produce_ts(b"not json"), None);
+    }
+}
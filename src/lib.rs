@@ -1,145 +1,534 @@
 use std::sync::atomic::Ordering;
 use std::sync::atomic::{AtomicBool, AtomicU64};
-use std::sync::Arc;
-use std::time::SystemTime;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
 
-use log::{error, info};
+use anyhow::{ensure, Result};
+
+use log::{error, info, warn};
 use parser::ServerConfig;
 use tokio::time;
 
+use crate::checkpoint::Checkpointer;
+use crate::generator::config::GeneratorConfig;
+use crate::generator::nexmark::config::NexmarkConfig;
+use crate::generator::nexmark::event::Event;
+use crate::generator::reorder::DisorderStats;
 use crate::generator::NexmarkGenerator;
-use crate::generator::{config::GeneratorConfig, source::NexmarkSource};
+use crate::pacer::Pacer;
+use crate::sink::{Distribution, Sink};
 
+pub mod bulk;
+pub mod checkpoint;
+pub mod format;
 pub mod generator;
+pub mod latency;
+pub mod load_profile;
+pub mod metrics;
+pub mod pacer;
 pub mod parser;
 pub mod producer;
+pub mod queries;
 pub mod server;
+pub mod sink;
+pub mod verify;
 
-const INTERVAL_CHECK_FREQUENCY: f64 = 10.0;
-const PRINT_FREQUENCY: f64 = 0.2;
+/// How often every generator logs its achieved rate.
+const PRINT_INTERVAL: time::Duration = time::Duration::from_secs(5);
+/// The longest a generator sleeps before checking whether it is stopped or paused.
+const PAUSE_CHECK_INTERVAL: time::Duration = time::Duration::from_millis(100);
+/// How long a generator of `run_generators` waits for its events to be delivered.
+const FLUSH_TIMEOUT: time::Duration = time::Duration::from_secs(5);
 
+/// The target rate of all the generators together, changed by `/nexmark/qps` and the load
+/// profiles.
 #[derive(Debug)]
 pub struct NexmarkInterval {
-    pub microseconds: AtomicU64,
+    /// The bits of the `f64` events per second.
+    rate: AtomicU64,
 }
 
 impl NexmarkInterval {
     pub fn new(config: &ServerConfig) -> Self {
+        metrics::TARGET_RATE.set(config.event_rate as f64);
         Self {
-            microseconds: AtomicU64::new(GeneratorConfig::get_event_delay_microseconds(
-                config.event_rate,
-                config.num_event_generators,
-            )),
+            rate: AtomicU64::new((config.event_rate as f64).to_bits()),
         }
     }
+
+    /// Returns the rate of all the generators together, in events per second.
+    pub fn rate(&self) -> f64 {
+        f64::from_bits(self.rate.load(Ordering::Relaxed))
+    }
+
+    /// Changes the rate of all the generators together, in events per second.
+    pub fn set_rate(&self, event_rate: f64) {
+        metrics::TARGET_RATE.set(event_rate);
+        self.rate.store(event_rate.to_bits(), Ordering::Relaxed);
+    }
 }
 
-/// Creates generators from config options and sends events directly to kafka
-pub async fn run_generators(
-    server_config: ServerConfig,
-    nexmark_source: Arc<NexmarkSource>,
-    running: Arc<AtomicBool>,
-    nexmark_interval: Arc<NexmarkInterval>,
+/// The state of the generators shared with the REST API, so that they can be paused, stopped or
+/// reconfigured while running.
+#[derive(Debug)]
+pub struct GeneratorControl {
+    /// Cleared to stop the generators, which then flush the sink and exit.
+    running: AtomicBool,
+    paused: AtomicBool,
+    skip_person: AtomicBool,
+    skip_auction: AtomicBool,
+    skip_bid: AtomicBool,
+    events_produced: Vec<AtomicU64>,
+    /// The disorder of the events of the generators that are done.
+    disorder: Mutex<DisorderStats>,
+    start_time: Instant,
+}
+
+impl GeneratorControl {
+    pub fn new(config: &ServerConfig) -> Self {
+        let skip = &config.skip_event_types;
+        Self {
+            running: AtomicBool::new(true),
+            paused: AtomicBool::new(false),
+            skip_person: AtomicBool::new(skip.contains("person")),
+            skip_auction: AtomicBool::new(skip.contains("auction")),
+            skip_bid: AtomicBool::new(skip.contains("bid")),
+            events_produced: (0..config.num_event_generators)
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            disorder: Mutex::default(),
+            start_time: Instant::now(),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// Returns whether the person, auction and bid events are skipped.
+    pub fn skipped_event_types(&self) -> (bool, bool, bool) {
+        (
+            self.skip_person.load(Ordering::Relaxed),
+            self.skip_auction.load(Ordering::Relaxed),
+            self.skip_bid.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Changes the skipped event types, in the format of `--skip-event-types`, e.g. `person,bid`.
+    pub fn set_skip_event_types(&self, skip_event_types: &str) -> Result<()> {
+        let skip = |event_type| skip_event_types.contains(event_type);
+        ensure!(
+            !(skip("person") && skip("auction") && skip("bid")),
+            "at least one event type must be generated"
+        );
+        self.skip_person.store(skip("person"), Ordering::Relaxed);
+        self.skip_auction.store(skip("auction"), Ordering::Relaxed);
+        self.skip_bid.store(skip("bid"), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the number of events produced by every generator.
+    pub fn events_produced(&self) -> Vec<u64> {
+        self.events_produced
+            .iter()
+            .map(|events| events.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Returns the disorder of the events of the generators that are done.
+    pub fn disorder_stats(&self) -> DisorderStats {
+        *self.disorder.lock().unwrap()
+    }
+
+    /// Adds the disorder of the events of a generator once it is done.
+    fn record_disorder(&self, stats: DisorderStats) {
+        self.disorder.lock().unwrap().merge(&stats);
+    }
+
+    pub fn uptime(&self) -> time::Duration {
+        self.start_time.elapsed()
+    }
+
+    /// Counts the events produced by a generator, before they are sent to the sink.
+    fn record_generated(&self, generator_idx: usize, events: &[Event]) {
+        self.events_produced[generator_idx].fetch_add(events.len() as u64, Ordering::Relaxed);
+        let generator_label = generator_idx.to_string();
+        for event_type in ["person", "auction", "bid"] {
+            let count = events
+                .iter()
+                .filter(|e| e.event_type() == event_type)
+                .count();
+            metrics::EVENTS_GENERATED
+                .with_label_values(&[&generator_label, event_type])
+                .inc_by(count as u64);
+        }
+    }
+}
+
+/// Creates a generator, starting from its checkpoint if any.
+fn new_generator(
+    config: GeneratorConfig,
+    generator_idx: usize,
+    checkpointer: Option<&Checkpointer>,
+) -> NexmarkGenerator {
+    let mut generator = NexmarkGenerator::new(config, generator_idx as u64);
+    if let Some(checkpointer) = checkpointer {
+        generator.resume(
+            checkpointer.local_events_so_far(generator_idx),
+            checkpointer.last_emitted(generator_idx),
+        );
+    }
+    generator
+}
+
+/// Waits until the events sent by a generator are delivered, and checkpoints its progress if
+/// enabled.
+fn flush(
+    sink: &dyn Sink,
+    generator_idx: usize,
+    generator: &NexmarkGenerator,
+    checkpointer: Option<&Checkpointer>,
+    timeout: time::Duration,
 ) {
+    let result = match checkpointer {
+        Some(checkpointer) => checkpointer.flush_and_save(
+            sink,
+            generator_idx,
+            generator.local_events_so_far(),
+            generator.last_emitted(),
+            timeout,
+        ),
+        None => sink.flush(generator_idx, timeout),
+    };
+    if let Err(err) = result {
+        error!("Producer_{} failed to flush: {:#}", generator_idx, err);
+    }
+}
+
+/// Logs a batch that failed to be sent. Its events may be lost, so the generator is no longer
+/// checkpointed.
+fn batch_failed(generator_idx: usize, err: anyhow::Error, checkpointer: Option<&Checkpointer>) {
+    error!("{:#}", err);
+    if let Some(checkpointer) = checkpointer {
+        checkpointer.poison(generator_idx);
+    }
+}
+
+/// Returns the config shared by all the generators, starting at the current wall clock time.
+fn generator_config(
+    server_config: &ServerConfig,
+    nexmark_config: NexmarkConfig,
+) -> GeneratorConfig {
     let wallclock_base_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
         .as_millis() as u64;
+    GeneratorConfig {
+        start_event: server_config.start_event,
+        ..GeneratorConfig::new(
+            nexmark_config,
+            server_config.max_events,
+            wallclock_base_time,
+            server_config.num_event_generators as u64,
+            server_config.skip_event_types.clone(),
+        )
+    }
+}
+
+/// Creates generators from config options and sends events directly to the sink
+pub async fn run_generators(
+    server_config: ServerConfig,
+    nexmark_config: NexmarkConfig,
+    sink: Arc<dyn Sink>,
+    control: Arc<GeneratorControl>,
+    nexmark_interval: Arc<NexmarkInterval>,
+    checkpointer: Option<Arc<Checkpointer>>,
+) {
     let mut handlers = Vec::<tokio::task::JoinHandle<()>>::new();
     let start_time = SystemTime::now();
-    let generator_config = GeneratorConfig::new(
-        server_config.max_events,
-        wallclock_base_time,
-        server_config.num_event_generators as u64,
-        server_config.skip_event_types,
-    );
+    let generator_config = generator_config(&server_config, nexmark_config);
 
     for generator_idx in 0..server_config.num_event_generators {
         let generator_config = generator_config.clone();
-        let running = running.clone();
-        let source = nexmark_source.clone();
-        let atomic_interval_supplied = nexmark_interval.clone();
+        let control = control.clone();
+        let sink = sink.clone();
+        let nexmark_interval = nexmark_interval.clone();
+        let checkpointer = checkpointer.clone();
+        let num_event_generators = server_config.num_event_generators as f64;
 
         let handler = tokio::spawn(async move {
-            let mut generator = NexmarkGenerator::new(generator_config, generator_idx as u64);
-            let mut interval = time::interval(time::Duration::from_micros(
-                atomic_interval_supplied
-                    .microseconds
-                    .load(Ordering::Relaxed),
-            ));
-            let mut new_interval = atomic_interval_supplied
-                .microseconds
-                .load(Ordering::Relaxed);
-
-            let mut loop_idx = 0;
-            let mut check_idx =
-                ((1_000_000 / (new_interval + 1)) as f64 / INTERVAL_CHECK_FREQUENCY).ceil() as u64;
-            let mut print_idx =
-                ((1_000_000 / (new_interval + 1)) as f64 / PRINT_FREQUENCY).ceil() as u64;
-            let mut timestamp = SystemTime::now();
+            let mut generator =
+                new_generator(generator_config, generator_idx, checkpointer.as_deref());
+            let generator_label = generator_idx.to_string();
+            let mut pacer = Pacer::new(
+                nexmark_interval.rate() / num_event_generators,
+                Instant::now(),
+            );
+            let mut print_time = Instant::now();
+            let mut print_events = 0;
+            let mut checkpoint_time = Instant::now();
 
             loop {
-                interval.tick().await;
-                loop_idx += 1;
-                // if ctrc has been received, terminate the thread
-                if !running.load(Ordering::SeqCst) {
+                // if ctrc or a POST to /nexmark/stop has been received, terminate the thread
+                if !control.is_running() {
                     break;
                 }
-
-                // update interval for controlling generating rate
-                if loop_idx % check_idx == 0 {
-                    // if the interval has been chanegd by a POST to /nexmark/qps, change interval in generator
-                    new_interval = atomic_interval_supplied
-                        .microseconds
-                        .load(Ordering::Relaxed);
-                    if interval.period().as_micros() as u64 != new_interval {
-                        interval = time::interval(time::Duration::from_micros(new_interval));
-                        check_idx = ((1_000_000 / (new_interval + 1)) as f64
-                            / INTERVAL_CHECK_FREQUENCY)
-                            .ceil() as u64;
-                        print_idx = ((1_000_000 / (new_interval + 1)) as f64 / PRINT_FREQUENCY)
-                            .ceil() as u64;
-                    }
+                // don't catch up on the events missed while paused once resumed
+                if control.is_paused() {
+                    time::sleep(PAUSE_CHECK_INTERVAL).await;
+                    pacer.reset(Instant::now());
+                    continue;
                 }
 
+                // the rate may have been changed by a POST to /nexmark/qps or a load profile
+                let now = Instant::now();
+                pacer.set_rate(nexmark_interval.rate() / num_event_generators, now);
+                let (skip_person, skip_auction, skip_bid) = control.skipped_event_types();
+                generator.set_skipped_event_types(skip_person, skip_auction, skip_bid);
+
                 // print real-time rate information
-                if loop_idx % print_idx == 0 {
-                    let elapse = SystemTime::elapsed(&timestamp).unwrap();
-                    let rate = print_idx as f64 / elapse.as_micros() as f64 * 1_000_000_f64;
+                if now - print_time >= PRINT_INTERVAL {
+                    let elapsed = now - print_time;
+                    let rate = print_events as f64 / elapsed.as_secs_f64();
                     info!(
-                        "Producer_{} produce {} event in {:?}, generate_rate: {} row/s",
-                        generator_idx, print_idx, elapse, rate
+                        "Producer_{} produced {} events in {:?}, target rate: {:.0} events/s, achieved rate: {:.0} events/s",
+                        generator_idx, print_events, elapsed, pacer.rate(), rate
                     );
-                    timestamp = SystemTime::now();
+                    metrics::ACHIEVED_RATE
+                        .with_label_values(&[&generator_label])
+                        .set(rate);
+                    print_time = now;
+                    print_events = 0;
+                }
+                if let Some(checkpointer) = &checkpointer {
+                    if now - checkpoint_time >= checkpointer.interval() {
+                        // flushing blocks until kafka acknowledges the events
+                        let (sink, checkpointer) = (sink.clone(), checkpointer.clone());
+                        generator = tokio::task::spawn_blocking(move || {
+                            flush(
+                                sink.as_ref(),
+                                generator_idx,
+                                &generator,
+                                Some(&checkpointer),
+                                FLUSH_TIMEOUT,
+                            );
+                            generator
+                        })
+                        .await
+                        .unwrap();
+                        checkpoint_time = Instant::now();
+                        continue;
+                    }
                 }
 
-                let next_event = generator.next_event();
-
-                match next_event {
-                    Some(next_e) => {
-                        let producer = source.get_producer_for_generator(generator_idx);
-                        let topic = producer.choose_topic(&next_e);
-                        let payload = producer.serialize_event(next_e);
-                        if let Err(err) = producer.send_data_to_topic(&payload, topic).await {
-                            error!("Error in sending event {:?}: {}", payload, &err);
-                        }
+                let due = pacer.due(now);
+                if due == 0 {
+                    time::sleep(pacer.until_next(now).min(PAUSE_CHECK_INTERVAL)).await;
+                    continue;
+                }
+                let events: Vec<Event> = (0..due).map_while(|_| generator.next_event()).collect();
+                let finished = (events.len() as u64) < due;
+                pacer.record(events.len() as u64);
+                print_events += events.len();
+                control.record_generated(generator_idx, &events);
+                if !events.is_empty() {
+                    if let Err(err) = sink.send_batch(generator_idx, events).await {
+                        batch_failed(generator_idx, err, checkpointer.as_deref());
                     }
-                    None => break,
-                };
+                }
+                if finished {
+                    break;
+                }
+                // let the other generators run when batches are due back to back
+                tokio::task::yield_now().await;
             }
-            source
-                .get_producer_for_generator(generator_idx)
-                .producer
-                .flush(time::Duration::new(5, 0));
+            // flushing blocks, on the files written or until kafka acknowledges the events
+            let generator = tokio::task::spawn_blocking(move || {
+                flush(
+                    sink.as_ref(),
+                    generator_idx,
+                    &generator,
+                    checkpointer.as_deref(),
+                    FLUSH_TIMEOUT,
+                );
+                generator
+            })
+            .await
+            .unwrap();
+            control.record_disorder(generator.disorder_stats());
         });
         handlers.push(handler);
     }
     for handler in handlers.into_iter() {
         handler.await.unwrap();
     }
+    if let Err(err) = sink.close().await {
+        error!("Failed to close the sink: {:#}", err);
+    }
+    let metrics = sink.metrics();
     info!(
-        "Delivered {} events in {:?}",
-        server_config.max_events,
+        "Delivered {} of the {} events sent ({} bytes, {} errors) in {:?}",
+        metrics.events_delivered,
+        metrics.events_sent,
+        metrics.bytes_sent,
+        metrics.send_errors,
         SystemTime::elapsed(&start_time).unwrap()
     );
+    info!("Disorder of the event times: {}", control.disorder_stats());
+    let destinations = sink.destinations();
+    if !destinations.is_empty() {
+        info!(
+            "Distribution of the events:\n{}",
+            Distribution(&destinations)
+        );
+    }
+    if metrics.events_delivered < metrics.events_sent {
+        warn!(
+            "{} events were not confirmed as delivered",
+            metrics.events_sent - metrics.events_delivered
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::checkpoint::Checkpoint;
+    use crate::generator::nexmark::event::Event;
+    use crate::generator::nexmark::properties::NexmarkProperties;
+    use crate::parser::{CheckpointConfig, Durability};
+    use crate::sink::{SinkCounters, SinkMetrics};
+
+    /// Keeps the events in memory so that the pipeline can be tested without kafka.
+    #[derive(Default)]
+    struct MemorySink {
+        events: Mutex<Vec<Event>>,
+        counters: SinkCounters,
+        /// Fails to send the batches of this generator.
+        failing_generator: Option<usize>,
+    }
+
+    #[async_trait]
+    impl Sink for MemorySink {
+        async fn open(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn send_batch(&self, generator_idx: usize, events: Vec<Event>) -> anyhow::Result<()> {
+            anyhow::ensure!(
+                self.failing_generator != Some(generator_idx),
+                "failed to send a batch"
+            );
+            for event in &events {
+                self.counters.record_sent(
+                    generator_idx,
+                    event.event_type(),
+                    event.to_json(true, Default::default()).len(),
+                );
+            }
+            self.events.lock().unwrap().extend(events);
+            Ok(())
+        }
+
+        fn flush(&self, _generator_idx: usize, _timeout: Duration) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn close(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn metrics(&self) -> SinkMetrics {
+            self.counters.snapshot()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_generators() {
+        let server_config = ServerConfig {
+            event_rate: 100_000,
+            max_events: 200,
+            num_event_generators: 4,
+            ..Default::default()
+        };
+        let nexmark_config = NexmarkConfig::from(NexmarkProperties::default()).unwrap();
+        let sink = Arc::new(MemorySink::default());
+        run_generators(
+            server_config.clone(),
+            nexmark_config,
+            sink.clone(),
+            Arc::new(GeneratorControl::new(&server_config)),
+            Arc::new(NexmarkInterval::new(&server_config)),
+            None,
+        )
+        .await;
+
+        let events = sink.events.lock().unwrap();
+        assert_eq!(events.len(), 200);
+        assert_eq!(sink.metrics().events_sent, 200);
+        let persons = events
+            .iter()
+            .filter(|e| matches!(e, Event::Person(_)))
+            .count();
+        let bids = events.iter().filter(|e| matches!(e, Event::Bid(_))).count();
+        // 1 person, 3 auctions and 46 bids out of every 50 events
+        assert_eq!(persons, 4);
+        assert_eq!(bids, 184);
+    }
+
+    #[tokio::test]
+    async fn test_failed_batch_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint.json");
+        let server_config = ServerConfig {
+            event_rate: 100_000,
+            max_events: 200,
+            num_event_generators: 2,
+            durability: Durability::Idempotent,
+            checkpoint: CheckpointConfig {
+                checkpoint_file: Some(path.clone()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let nexmark_config = NexmarkConfig::from(NexmarkProperties::default()).unwrap();
+        let sink = Arc::new(MemorySink {
+            failing_generator: Some(1),
+            ..Default::default()
+        });
+        let checkpointer = Checkpointer::new(&server_config).unwrap().map(Arc::new);
+        run_generators(
+            server_config.clone(),
+            nexmark_config,
+            sink.clone(),
+            Arc::new(GeneratorControl::new(&server_config)),
+            Arc::new(NexmarkInterval::new(&server_config)),
+            checkpointer,
+        )
+        .await;
+
+        assert_eq!(sink.events.lock().unwrap().len(), 100);
+        // the generator whose batches failed resumes from its first event
+        assert_eq!(
+            Checkpoint::load(&path).unwrap().local_events_so_far,
+            [100, 0]
+        );
+    }
 }
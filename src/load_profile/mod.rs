@@ -0,0 +1,254 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::{bail, ensure, Context, Result};
+use log::info;
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::generator::nexmark::config::NexmarkConfig;
+use crate::NexmarkInterval;
+
+/// How often the scheduler updates the rate, which bounds the granularity of the ramps.
+const SCHEDULER_TICK: time::Duration = time::Duration::from_millis(100);
+
+/// A script of event rates over time, e.g. 10k events/s for 60s, then a ramp to 500k events/s
+/// over 120s.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoadProfile {
+    pub phases: Vec<Phase>,
+    /// Starts again from the first phase once the last one is over. Otherwise the rate of the
+    /// last phase is kept.
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Phase {
+    /// Holds a rate. `burst` is the same, meant for short spikes.
+    #[serde(alias = "burst")]
+    Step { rate: usize, duration_secs: f64 },
+    /// Goes linearly from one rate to another.
+    Ramp {
+        from: usize,
+        to: usize,
+        duration_secs: f64,
+    },
+}
+
+impl Phase {
+    fn duration_secs(&self) -> f64 {
+        match self {
+            Phase::Step { duration_secs, .. } | Phase::Ramp { duration_secs, .. } => *duration_secs,
+        }
+    }
+
+    fn rate_at(&self, elapsed_secs: f64) -> usize {
+        match *self {
+            Phase::Step { rate, .. } => rate,
+            Phase::Ramp {
+                from,
+                to,
+                duration_secs,
+            } => {
+                let progress = elapsed_secs / duration_secs;
+                (from as f64 + (to as f64 - from as f64) * progress).round() as usize
+            }
+        }
+    }
+}
+
+impl LoadProfile {
+    /// Reads a profile from a JSON, YAML or TOML file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read load profile {}", path.display()))?;
+        let profile: LoadProfile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content)?,
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&content)?,
+            Some("toml") => toml::from_str(&content)?,
+            _ => bail!(
+                "load profile {} should end with .json, .yaml, .yml or .toml",
+                path.display()
+            ),
+        };
+        profile
+            .validate()
+            .with_context(|| format!("invalid load profile {}", path.display()))?;
+        Ok(profile)
+    }
+
+    /// Builds the profile following the `inter_event_delays` of the sine or square rate shape,
+    /// each of them lasting `step_length` seconds, or `None` for a flat rate.
+    pub fn from_rate_shape(nexmark_config: &NexmarkConfig) -> Option<Self> {
+        if nexmark_config.inter_event_delays.len() <= 1 {
+            return None;
+        }
+        // the delays are computed for `threads` generators
+        let phases = nexmark_config
+            .inter_event_delays
+            .iter()
+            .map(|delay| Phase::Step {
+                rate: (1_000_000_f32 * nexmark_config.num_event_generators as f32 / delay).round()
+                    as usize,
+                duration_secs: nexmark_config.step_length as f64,
+            })
+            .collect();
+        Some(Self {
+            phases,
+            repeat: true,
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.phases.is_empty(), "a load profile needs phases");
+        for phase in &self.phases {
+            let duration = phase.duration_secs();
+            ensure!(
+                duration.is_finite() && duration > 0.0,
+                "the duration of {:?} should be positive",
+                phase
+            );
+            match *phase {
+                Phase::Step { rate, .. } => ensure!(rate > 0, "rates should be positive"),
+                Phase::Ramp { from, to, .. } => {
+                    ensure!(from > 0 && to > 0, "rates should be positive")
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the index of the phase and the rate after `elapsed_secs`, or `None` once a profile
+    /// that does not repeat is over.
+    pub fn rate_at(&self, elapsed_secs: f64) -> Option<(usize, usize)> {
+        let total: f64 = self.phases.iter().map(Phase::duration_secs).sum();
+        let mut elapsed = match self.repeat {
+            true => elapsed_secs % total,
+            false if elapsed_secs >= total => return None,
+            false => elapsed_secs,
+        };
+        for (idx, phase) in self.phases.iter().enumerate() {
+            if elapsed < phase.duration_secs() {
+                return Some((idx, phase.rate_at(elapsed)));
+            }
+            elapsed -= phase.duration_secs();
+        }
+        // rounding errors at the very end of the last phase
+        let last = self.phases.len() - 1;
+        Some((
+            last,
+            self.phases[last].rate_at(self.phases[last].duration_secs()),
+        ))
+    }
+}
+
+/// Runs at most one load profile at a time, updating the interval of the generators.
+pub struct LoadProfileScheduler {
+    nexmark_interval: Arc<NexmarkInterval>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl LoadProfileScheduler {
+    pub fn new(nexmark_interval: Arc<NexmarkInterval>) -> Self {
+        Self {
+            nexmark_interval,
+            handle: Mutex::new(None),
+        }
+    }
+
+    /// Starts a profile from its first phase, replacing the running one.
+    pub fn start(&self, profile: LoadProfile) {
+        let handle = tokio::spawn(run_load_profile(profile, self.nexmark_interval.clone()));
+        if let Some(previous) = self.handle.lock().unwrap().replace(handle) {
+            previous.abort();
+        }
+    }
+
+    /// Stops the running profile, keeping its current rate.
+    pub fn stop(&self) {
+        if let Some(handle) = self.handle.lock().unwrap().take() {
+            handle.abort();
+        }
+    }
+}
+
+async fn run_load_profile(profile: LoadProfile, nexmark_interval: Arc<NexmarkInterval>) {
+    let start = Instant::now();
+    let mut current = None;
+    let mut current_phase = None;
+    while let Some((phase, rate)) = profile.rate_at(start.elapsed().as_secs_f64()) {
+        if current_phase != Some(phase) {
+            info!(
+                "Load profile: phase {} {:?}, {} events/s",
+                phase, profile.phases[phase], rate
+            );
+            current_phase = Some(phase);
+        }
+        if current != Some(rate) {
+            nexmark_interval.set_rate(rate as f64);
+            current = Some(rate);
+        }
+        time::sleep(SCHEDULER_TICK).await;
+    }
+    info!("Load profile finished, keeping {:?} events/s", current);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(repeat: bool) -> LoadProfile {
+        serde_json::from_str(&format!(
+            r#"{{
+                "repeat": {},
+                "phases": [
+                    {{"type": "step", "rate": 10000, "duration_secs": 60}},
+                    {{"type": "ramp", "from": 10000, "to": 500000, "duration_secs": 120}},
+                    {{"type": "burst", "rate": 1000000, "duration_secs": 5}},
+                    {{"type": "step", "rate": 50000, "duration_secs": 15}}
+                ]
+            }}"#,
+            repeat
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rate_at() {
+        let once = profile(false);
+        once.validate().unwrap();
+        assert_eq!(once.rate_at(0.0), Some((0, 10_000)));
+        assert_eq!(once.rate_at(59.9), Some((0, 10_000)));
+        assert_eq!(once.rate_at(60.0), Some((1, 10_000)));
+        assert_eq!(once.rate_at(120.0), Some((1, 255_000)));
+        assert_eq!(once.rate_at(182.0), Some((2, 1_000_000)));
+        assert_eq!(once.rate_at(190.0), Some((3, 50_000)));
+        assert_eq!(once.rate_at(200.0), None);
+
+        let repeated = profile(true);
+        assert_eq!(repeated.rate_at(200.0), Some((0, 10_000)));
+        assert_eq!(repeated.rate_at(390.0), Some((3, 50_000)));
+    }
+
+    #[test]
+    fn test_invalid_profiles() {
+        let invalid = [
+            r#"{"phases": []}"#,
+            r#"{"phases": [{"type": "step", "rate": 0, "duration_secs": 1}]}"#,
+            r#"{"phases": [{"type": "ramp", "from": 1, "to": 2, "duration_secs": 0}]}"#,
+        ];
+        for profile in invalid {
+            let profile: LoadProfile = serde_json::from_str(profile).unwrap();
+            assert!(profile.validate().is_err(), "{:?}", profile);
+        }
+        assert!(serde_json::from_str::<LoadProfile>(
+            r#"{"phases": [{"type": "sine", "rate": 1, "duration_secs": 1}]}"#
+        )
+        .is_err());
+    }
+}
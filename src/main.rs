@@ -1,59 +1,150 @@
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
-use clap::Parser;
+use anyhow::Context;
 use core::time;
+use log::info;
 use rocket::routes;
 use rocket::Config as RocketConfig;
 
+use nexmark_server::bulk::run_bulk;
+use nexmark_server::checkpoint::Checkpointer;
+use nexmark_server::generator::nexmark::config::NexmarkConfig;
+use nexmark_server::generator::nexmark::side_input::SideInputRow;
 use nexmark_server::generator::source::NexmarkSource;
-use nexmark_server::parser::ServerConfig;
+use nexmark_server::latency;
+use nexmark_server::load_profile::{LoadProfile, LoadProfileScheduler};
+use nexmark_server::parser::{Command, EventFormat, ProduceTimestamp, ServerConfig, SinkType};
+use nexmark_server::queries;
 use nexmark_server::run_generators;
-use nexmark_server::server::qps;
-use nexmark_server::NexmarkInterval;
+use nexmark_server::server::{
+    generator_status, load_profile, pause, prometheus_metrics, qps, resume, skip_event_types, stop,
+};
+use nexmark_server::sink::build_sink;
+use nexmark_server::verify;
+use nexmark_server::{GeneratorControl, NexmarkInterval};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     env_logger::init();
 
-    let running = Arc::new(AtomicBool::new(true));
-    let r = running.clone();
+    let conf = ServerConfig::from_args();
+    anyhow::ensure!(
+        conf.max_events == 0 || conf.start_event <= conf.max_events,
+        "--start-event {} is beyond --max-events {}",
+        conf.start_event,
+        conf.max_events
+    );
+    anyhow::ensure!(
+        conf.produce_timestamp == ProduceTimestamp::None || conf.sink == SinkType::Kafka,
+        "--produce-timestamp needs --sink kafka"
+    );
+    anyhow::ensure!(
+        conf.produce_timestamp != ProduceTimestamp::Field || conf.format == EventFormat::Json,
+        "--produce-timestamp field is only written by the json format, use header instead"
+    );
+    let control = Arc::new(GeneratorControl::new(&conf));
+    let c = control.clone();
     ctrlc::set_handler(move || {
-        r.store(false, Ordering::SeqCst);
+        c.stop();
     })
     .expect("Error setting Ctrl-C handler");
-    let conf = ServerConfig::parse();
-    let nexmark_source = Arc::new(NexmarkSource::new(&conf));
+    let nexmark_config = NexmarkConfig::from(conf.nexmark_properties()?)?;
+    info!("Effective nexmark properties:\n{}", nexmark_config);
+    if let Some(Command::Verify(verify_config)) = &conf.command {
+        let report = verify::run(conf.clone(), nexmark_config, verify_config.clone()).await?;
+        print!("{}", report);
+        anyhow::ensure!(report.passed(), "the verification failed");
+        return Ok(());
+    }
+    if let Some(Command::Latency(latency_config)) = &conf.command {
+        let report = latency::run(latency_config.clone(), control.clone()).await?;
+        print!("{}", report);
+        return Ok(());
+    }
+    if let Some(Command::Expected(expected_config)) = &conf.command {
+        for (query, rows) in queries::run(&conf, nexmark_config, expected_config)? {
+            println!("{}: {} rows", query, rows);
+        }
+        return Ok(());
+    }
     let interval = Arc::new(NexmarkInterval::new(&conf));
     match &conf.create_topic {
-        true => tokio::time::timeout(time::Duration::from_secs(10), nexmark_source.create_topic())
-            .await
-            .map_err(|_| {
-                anyhow::Error::msg(
-                    "Timed out while creating topic. Ensure the infra is up and running",
-                )
-            })
-            .unwrap(),
+        true => tokio::time::timeout(
+            time::Duration::from_secs(10),
+            NexmarkSource::new(&conf).create_topic(),
+        )
+        .await
+        .map_err(|_| {
+            anyhow::Error::msg("Timed out while creating topic. Ensure the infra is up and running")
+        })
+        .unwrap(),
         false => {
             let config = RocketConfig {
                 address: IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
                 port: conf.listen_port,
                 ..Default::default()
             };
-            nexmark_source.check_topic_exist().await?;
+            let sink = build_sink(&conf);
+            sink.open().await?;
+            let checkpointer = Checkpointer::new(&conf)?.map(Arc::new);
+            // a resumed run already sent the side input before the crash
+            if conf.side_input && !conf.checkpoint.resume {
+                let rows = SideInputRow::generate(&nexmark_config);
+                sink.send_side_input(&rows)
+                    .await
+                    .context("failed to send the side input")?;
+                info!("Sent the {} rows of the side input", rows.len());
+            }
+            let scheduler = Arc::new(LoadProfileScheduler::new(Arc::clone(&interval)));
+            let profile = match &conf.load_profile {
+                Some(path) => Some(LoadProfile::load(path)?),
+                None => LoadProfile::from_rate_shape(&nexmark_config),
+            };
+            if let (Some(profile), false) = (profile, conf.bulk) {
+                scheduler.start(profile);
+            }
             let rocket = rocket::custom(&config)
                 .manage(Arc::clone(&interval))
+                .manage(Arc::clone(&control))
+                .manage(Arc::clone(&scheduler))
                 .manage(conf.clone())
-                .mount("/nexmark", routes![qps])
+                .mount(
+                    "/nexmark",
+                    routes![
+                        qps,
+                        generator_status,
+                        pause,
+                        resume,
+                        stop,
+                        skip_event_types,
+                        load_profile
+                    ],
+                )
+                .mount("/", routes![prometheus_metrics])
                 .ignite()
                 .await
                 .unwrap();
             let shutdown_handle = rocket.shutdown();
             tokio::spawn(async move { rocket.launch().await.unwrap() });
-            run_generators(conf, nexmark_source, running.clone(), interval.clone()).await;
+            if conf.bulk {
+                let summary =
+                    run_bulk(conf, nexmark_config, sink, control.clone(), checkpointer).await;
+                // printed on stderr to be seen without RUST_LOG and kept apart from `--sink stdout`
+                eprint!("{}", summary);
+            } else {
+                run_generators(
+                    conf,
+                    nexmark_config,
+                    sink,
+                    control.clone(),
+                    interval.clone(),
+                    checkpointer,
+                )
+                .await;
+            }
+            scheduler.stop();
             shutdown_handle.notify();
         }
     }
@@ -0,0 +1,108 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    register_gauge, register_gauge_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    Gauge, GaugeVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+
+lazy_static! {
+    pub static ref EVENTS_GENERATED: IntCounterVec = register_int_counter_vec!(
+        "nexmark_events_generated_total",
+        "Events generated, by generator and event type.",
+        &["generator", "event_type"]
+    )
+    .unwrap();
+    pub static ref EVENTS_SENT: IntCounterVec = register_int_counter_vec!(
+        "nexmark_events_sent_total",
+        "Events handed to the sink, by generator and event type.",
+        &["generator", "event_type"]
+    )
+    .unwrap();
+    pub static ref BYTES_SERIALIZED: IntCounterVec = register_int_counter_vec!(
+        "nexmark_bytes_serialized_total",
+        "Bytes of the serialized events handed to the sink, by generator and event type.",
+        &["generator", "event_type"]
+    )
+    .unwrap();
+    pub static ref EVENTS_OUT_OF_ORDER: IntCounterVec = register_int_counter_vec!(
+        "nexmark_events_out_of_order_total",
+        "Events emitted after an event with a later event time, by generator and event type.",
+        &["generator", "event_type"]
+    )
+    .unwrap();
+    pub static ref EVENTS_LATE: IntCounterVec = register_int_counter_vec!(
+        "nexmark_events_late_total",
+        "Events emitted more than max_delay_ms behind the latest event time, by generator and event type.",
+        &["generator", "event_type"]
+    )
+    .unwrap();
+    pub static ref MAX_EVENT_LATENESS: IntGaugeVec = register_int_gauge_vec!(
+        "nexmark_max_event_lateness_ms",
+        "Longest an event was emitted behind the latest event time, by generator.",
+        &["generator"]
+    )
+    .unwrap();
+    pub static ref SEND_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "nexmark_send_errors_total",
+        "Events the sink failed to send, by generator and event type.",
+        &["generator", "event_type"]
+    )
+    .unwrap();
+    pub static ref QUEUE_FULL_RETRIES: IntCounterVec = register_int_counter_vec!(
+        "nexmark_kafka_queue_full_retries_total",
+        "Sends retried because the local queue of the kafka producer was full, by generator.",
+        &["generator"]
+    )
+    .unwrap();
+    pub static ref DELIVERY_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "nexmark_kafka_delivery_failures_total",
+        "Messages kafka failed to deliver, by generator.",
+        &["generator"]
+    )
+    .unwrap();
+    pub static ref PRODUCER_QUEUE_DEPTH: IntGaugeVec = register_int_gauge_vec!(
+        "nexmark_kafka_producer_queue_depth",
+        "Messages waiting in the kafka producer queue or in flight, by generator.",
+        &["generator"]
+    )
+    .unwrap();
+    pub static ref TARGET_RATE: Gauge = register_gauge!(
+        "nexmark_target_event_rate",
+        "Events per second the generators are asked to produce."
+    )
+    .unwrap();
+    pub static ref ACHIEVED_RATE: GaugeVec = register_gauge_vec!(
+        "nexmark_achieved_event_rate",
+        "Events per second recently produced, by generator.",
+        &["generator"]
+    )
+    .unwrap();
+}
+
+/// Renders all the metrics in the Prometheus text format.
+pub fn gather() -> String {
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::SinkCounters;
+
+    #[test]
+    fn test_gather() {
+        let counters = SinkCounters::default();
+        counters.record_sent(7, "bid", 100);
+        counters.record_sent(7, "bid", 50);
+        counters.record_error(7, "person");
+        let text = gather();
+        assert!(text.contains(r#"nexmark_events_sent_total{event_type="bid",generator="7"} 2"#));
+        assert!(
+            text.contains(r#"nexmark_bytes_serialized_total{event_type="bid",generator="7"} 150"#)
+        );
+        assert!(text.contains(r#"nexmark_send_errors_total{event_type="person",generator="7"} 1"#));
+    }
+}
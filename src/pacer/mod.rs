@@ -0,0 +1,119 @@
+use std::time::{Duration, Instant};
+
+/// The most events a generator produces and sends to the sink at once.
+pub const MAX_BATCH_SIZE: u64 = 1024;
+/// How far behind the wall clock a generator may fall before its backlog is dropped, so that it
+/// doesn't burst for long after a stall of the sink.
+const MAX_BACKLOG: Duration = Duration::from_secs(1);
+
+/// A token bucket pacing the events of a generator against the wall clock.
+///
+/// Instead of waiting for one tick per event, the generator asks how many events are due since
+/// the rate was last set and sends them as a micro-batch, so that rounding errors and late
+/// wake-ups don't accumulate and rates of millions of events per second can be reached.
+#[derive(Debug)]
+pub struct Pacer {
+    /// Events per second.
+    rate: f64,
+    epoch: Instant,
+    /// Events produced since `epoch`.
+    produced: u64,
+}
+
+impl Pacer {
+    pub fn new(rate: f64, now: Instant) -> Self {
+        Self {
+            rate,
+            epoch: now,
+            produced: 0,
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Changes the rate, starting a new epoch so that the previous rate doesn't leave a backlog.
+    pub fn set_rate(&mut self, rate: f64, now: Instant) {
+        if rate != self.rate {
+            self.rate = rate;
+            self.reset(now);
+        }
+    }
+
+    /// Forgets the events that were due, e.g. while the generators were paused.
+    pub fn reset(&mut self, now: Instant) {
+        self.epoch = now;
+        self.produced = 0;
+    }
+
+    /// Returns the number of events due at `now`, at most `MAX_BATCH_SIZE`.
+    pub fn due(&mut self, now: Instant) -> u64 {
+        let elapsed = now.saturating_duration_since(self.epoch);
+        let mut allowed = (elapsed.as_secs_f64() * self.rate) as u64;
+        if allowed.saturating_sub(self.produced) as f64 > MAX_BACKLOG.as_secs_f64() * self.rate {
+            self.reset(now - MAX_BACKLOG);
+            allowed = (MAX_BACKLOG.as_secs_f64() * self.rate) as u64;
+        }
+        allowed.saturating_sub(self.produced).min(MAX_BATCH_SIZE)
+    }
+
+    pub fn record(&mut self, events: u64) {
+        self.produced += events;
+    }
+
+    /// Returns how long to wait from `now` until the next event is due.
+    pub fn until_next(&self, now: Instant) -> Duration {
+        if self.rate <= 0.0 {
+            return Duration::MAX;
+        }
+        let next = self.epoch + Duration::from_secs_f64((self.produced + 1) as f64 / self.rate);
+        next.saturating_duration_since(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_due() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(3_000_000.0, start);
+        assert_eq!(pacer.due(start), 0);
+        assert_eq!(pacer.until_next(start), Duration::from_nanos(333));
+
+        // 3M events/s for 10ms are produced in batches without drifting
+        let mut produced = 0;
+        for micros in (0..=10_000).step_by(100) {
+            let due = pacer.due(start + Duration::from_micros(micros));
+            pacer.record(due);
+            produced += due;
+        }
+        assert_eq!(produced, 30_000);
+        assert_eq!(pacer.due(start + Duration::from_millis(20)), MAX_BATCH_SIZE);
+
+        // a new rate doesn't inherit the backlog of the previous one
+        let now = start + Duration::from_millis(20);
+        pacer.set_rate(10.0, now);
+        assert_eq!(pacer.due(now), 0);
+        assert_eq!(pacer.until_next(now), Duration::from_millis(100));
+        assert_eq!(pacer.due(now + Duration::from_millis(250)), 2);
+    }
+
+    #[test]
+    fn test_max_backlog() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(100.0, start);
+        let mut produced = 0;
+        loop {
+            let due = pacer.due(start + Duration::from_secs(10));
+            if due == 0 {
+                break;
+            }
+            pacer.record(due);
+            produced += due;
+        }
+        assert_eq!(produced, 100);
+    }
+}
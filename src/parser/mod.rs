@@ -1,39 +1,378 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::format::SerializeOptions;
+use crate::generator::nexmark::properties::NexmarkProperties;
 
 #[derive(Clone, Debug, Parser)]
 pub struct ServerConfig {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     #[clap(long, default_value = "1000")]
     pub event_rate: usize,
 
     /// 0 is unlimited.
-    #[clap(long, default_value = "100")]
+    #[clap(long, global = true, default_value = "100")]
     pub max_events: u64,
 
-    /// Number of event generators
+    /// The id of the first event, to resume a run interrupted at this event with the same output.
+    /// `--max-events` still counts from the event 0.
+    #[clap(long, global = true, default_value = "0")]
+    pub start_event: u64,
+
+    /// Perturbs the random content of all the events, 0 is the content of the original Nexmark.
+    /// Overrides the `seed` nexmark property.
+    #[clap(long, global = true)]
+    pub seed: Option<u64>,
+
+    /// Number of event generators, 0 is one per CPU core.
     #[clap(long, default_value = "3")]
     pub num_event_generators: usize,
 
+    /// Generates `--max-events` as fast as possible on one thread per generator, ignoring
+    /// `--event-rate` and the load profiles, then prints a summary.
+    #[clap(long, action)]
+    pub bulk: bool,
+
+    /// Writes the side input of Query 13 before the events, to `SIDE_INPUT_TOPIC` for kafka or
+    /// to `side_input.{csv,jsonl}` for the file sink. With `--create-topic`, also creates
+    /// `SIDE_INPUT_TOPIC`.
+    #[clap(long, action)]
+    pub side_input: bool,
+
     /// The port listening.
     #[clap(long, default_value = "8000")]
     pub listen_port: u16,
 
     /// The event type to skip, e.g. "auction,person" means only produce bid events.
-    #[clap(long, default_value = "")]
+    #[clap(long, global = true, default_value = "")]
     pub skip_event_types: String,
 
     #[clap(long, short, action)]
     pub create_topic: bool,
+
+    /// Where the events are sent to.
+    #[clap(long, value_enum, default_value = "kafka")]
+    pub sink: SinkType,
+
+    /// The delivery guarantees of the kafka producers.
+    #[clap(long, value_enum, default_value = "fast")]
+    pub durability: Durability,
+
+    /// Embeds the wall-clock time every event is sent at, in ms since epoch, to measure the
+    /// end-to-end latency with `nexmark-server latency`.
+    #[clap(long, value_enum, default_value = "none")]
+    pub produce_timestamp: ProduceTimestamp,
+
+    /// How the events are spread over the partitions of the kafka topics.
+    #[clap(long, value_enum, default_value = "round-robin")]
+    pub partitioning: Partitioning,
+
+    /// The encoding of the events sent to kafka.
+    #[clap(long, global = true, value_enum, default_value = "json")]
+    pub format: EventFormat,
+
+    /// How the `date_time` and `expires` fields are written.
+    #[clap(long, global = true, value_enum, default_value = "string")]
+    pub timestamp_format: TimestampFormat,
+
+    /// How the fields of the events are named, to match the DDL of the target system.
+    #[clap(long, global = true, value_enum, default_value = "snake-case")]
+    pub field_naming: FieldNaming,
+
+    #[clap(flatten)]
+    pub file_sink: FileSinkConfig,
+
+    #[clap(flatten)]
+    pub checkpoint: CheckpointConfig,
+
+    /// A JSON, YAML or TOML file describing the rate over time, see `LoadProfile`. Replaces
+    /// the rate shape of the nexmark properties.
+    #[clap(long)]
+    pub load_profile: Option<PathBuf>,
+
+    /// A TOML or YAML file setting nexmark properties, e.g. `active_people = 1000`.
+    #[clap(long, global = true)]
+    pub config_file: Option<PathBuf>,
+
+    /// Overrides a nexmark property, e.g. `--property hot_seller_ratio=8`. Takes precedence
+    /// over the config file and the `NEXMARK_*` env vars. Can be repeated.
+    #[clap(long = "property", global = true, value_name = "KEY=VALUE")]
+    pub properties: Vec<String>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Reads back the kafka topics instead of generating events, and checks that they hold the
+    /// events generated with the same options, e.g. `--max-events`, without losses, duplicates
+    /// or invalid records.
+    Verify(VerifyConfig),
+    /// Computes the results the queries of `risingwave/create_sinks.sql` should have on the
+    /// events generated with the same options, e.g. `--max-events`, instead of sending them.
+    Expected(ExpectedConfig),
+    /// Reads the records a system under test writes to a kafka topic, and reports the latency
+    /// since the events they come from were sent with `--produce-timestamp`.
+    Latency(LatencyConfig),
+}
+
+/// Options of `nexmark-server verify`.
+#[derive(Clone, Debug, Args)]
+pub struct VerifyConfig {
+    /// Gives up when no record is read for this long before the end of the topics.
+    #[clap(long, default_value = "30")]
+    pub idle_timeout_secs: u64,
+}
+
+/// Options of `nexmark-server expected`.
+#[derive(Clone, Debug, Args)]
+pub struct ExpectedConfig {
+    /// The directory the results are written to, as one `{query}.csv` file per query.
+    #[clap(long, default_value = "expected")]
+    pub output_dir: PathBuf,
+
+    /// The queries to evaluate, e.g. `q0,q5,q101`. All of them by default.
+    #[clap(long, value_delimiter = ',')]
+    pub queries: Vec<String>,
+}
+
+/// Options of `nexmark-server latency`.
+#[derive(Clone, Debug, Args)]
+pub struct LatencyConfig {
+    /// The topic the results of the system under test are written to.
+    #[clap(long)]
+    pub topic: String,
+
+    /// How often the percentiles of the latencies read since the previous report are printed.
+    #[clap(long, default_value = "10")]
+    pub report_interval_secs: u64,
+
+    /// Stops after this long, 0 is until Ctrl-C.
+    #[clap(long, default_value = "0")]
+    pub duration_secs: u64,
+
+    /// Reads the topic from the beginning instead of only the records written from now on.
+    #[clap(long, action)]
+    pub from_beginning: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SinkType {
+    /// The topics configured by the env vars, see `.env.example`.
+    Kafka,
+    /// One line of JSON per event on the standard output.
+    Stdout,
+    /// Local files, see `FileSinkConfig`.
+    File,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Durability {
+    /// `acks=0` without retries, events may be lost silently.
+    Fast,
+    /// An idempotent producer with `acks=all`, retrying without duplicates.
+    Idempotent,
+    /// Idempotent, and every batch of a generator is sent in a transaction, with the
+    /// `transactional.id` `{BASE_TOPIC}-{generator_idx}`.
+    Transactional,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Partitioning {
+    /// Every generator cycles through the partitions, separately for every event type, without
+    /// keys.
+    RoundRobin,
+    /// The events of a generator all go to the partition `generator_idx % NUM_PARTITIONS`, with
+    /// the key `event-{generator_idx}`, which keeps them in order.
+    PerGenerator,
+    /// The events are keyed by the id of their person or auction, or by the auction of a bid, and
+    /// partitioned by the murmur2 hash of the key like the Java clients, so that the auctions and
+    /// their bids are co-partitioned.
+    ByKey,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ProduceTimestamp {
+    None,
+    /// A `produce_ts` header on every kafka record.
+    Header,
+    /// A `produce_ts` field after the `extra` field of every person, auction and bid, only with
+    /// the json format.
+    Field,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EventFormat {
+    Json,
+    /// Avro in the Confluent wire format, with the schemas registered in `SCHEMA_REGISTRY_URL`.
+    Avro,
+    /// The messages of `proto/nexmark.proto`.
+    Protobuf,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TimestampFormat {
+    /// A string without time zone, e.g. `2015-07-15 00:00:00.001`.
+    #[default]
+    String,
+    /// An integer number of milliseconds since the UNIX epoch.
+    EpochMillis,
+    /// An integer number of microseconds since the UNIX epoch.
+    EpochMicros,
+    /// An RFC 3339 string in UTC, e.g. `2015-07-15T00:00:00.001Z`.
+    Rfc3339,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum FieldNaming {
+    /// `email_address`, as in the RisingWave DDL in `risingwave/`.
+    #[default]
+    SnakeCase,
+    /// `emailAddress`, as in the Flink and Beam Nexmark, e.g. the DDL in `flink/`.
+    CamelCase,
+}
+
+/// Options of `--sink file`.
+#[derive(Clone, Debug, Args)]
+pub struct FileSinkConfig {
+    /// The directory the files are written to.
+    #[clap(long, default_value = "nexmark-data")]
+    pub output_dir: PathBuf,
+
+    #[clap(long, value_enum, default_value = "jsonl")]
+    pub file_format: FileFormat,
+
+    /// Writes one file per event type instead of a combined file, like `SEPARATE_TOPICS`.
+    #[clap(long, action)]
+    pub separate_files: bool,
+
+    /// Starts a new file once this many events are written to the current one. 0 is unlimited.
+    #[clap(long, default_value = "0")]
+    pub rotate_events: u64,
+
+    /// Starts a new file once this many bytes, before compression, are written to the current
+    /// one. 0 is unlimited.
+    #[clap(long, default_value = "0")]
+    pub rotate_bytes: u64,
+
+    #[clap(long, value_enum, default_value = "none")]
+    pub compression: FileCompression,
+}
+
+impl Default for FileSinkConfig {
+    fn default() -> Self {
+        FileSinkConfig {
+            output_dir: PathBuf::from("nexmark-data"),
+            file_format: FileFormat::Jsonl,
+            separate_files: false,
+            rotate_events: 0,
+            rotate_bytes: 0,
+            compression: FileCompression::None,
+        }
+    }
+}
+
+/// Options of the checkpoints, see `Checkpointer`.
+#[derive(Clone, Debug, Args)]
+pub struct CheckpointConfig {
+    /// Persists the progress of every generator to this file once the brokers acknowledged its
+    /// events, to resume after a crash. Requires `--durability idempotent` or `transactional`.
+    #[clap(long)]
+    pub checkpoint_file: Option<PathBuf>,
+
+    /// How often every generator waits for its events to be delivered and persists its progress.
+    #[clap(long, default_value = "10")]
+    pub checkpoint_interval_secs: u64,
+
+    /// Resumes from the progress in `--checkpoint-file` instead of `--start-event`.
+    #[clap(long, action, requires = "checkpoint_file")]
+    pub resume: bool,
+}
+
+impl Default for CheckpointConfig {
+    fn default() -> Self {
+        CheckpointConfig {
+            checkpoint_file: None,
+            checkpoint_interval_secs: 10,
+            resume: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FileFormat {
+    /// One JSON object per line, as sent to kafka.
+    Jsonl,
+    /// Comma separated values, with a header when the event types are in separate files.
+    Csv,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FileCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl ServerConfig {
+    /// Parses the command line, resolving the defaults that depend on the machine.
+    pub fn from_args() -> Self {
+        let mut config = ServerConfig::parse();
+        if config.num_event_generators == 0 {
+            config.num_event_generators =
+                std::thread::available_parallelism().map_or(1, |n| n.get());
+        }
+        config
+    }
+
+    pub fn serialize_options(&self) -> SerializeOptions {
+        SerializeOptions {
+            timestamp_format: self.timestamp_format,
+            field_naming: self.field_naming,
+        }
+    }
+
+    pub fn nexmark_properties(&self) -> anyhow::Result<NexmarkProperties> {
+        let mut properties =
+            NexmarkProperties::load(self.config_file.as_deref(), &self.properties)?;
+        // event timestamps only track the wall clock if they advance as fast as events are sent
+        if properties.use_real_time && properties.first_event_rate.is_none() {
+            properties.first_event_rate = Some(self.event_rate);
+        }
+        if self.seed.is_some() {
+            properties.seed = self.seed;
+        }
+        Ok(properties)
+    }
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         ServerConfig {
+            command: None,
             max_events: 100,
+            start_event: 0,
+            seed: None,
             create_topic: false,
+            sink: SinkType::Kafka,
+            durability: Durability::Fast,
+            produce_timestamp: ProduceTimestamp::None,
+            partitioning: Partitioning::RoundRobin,
+            format: EventFormat::Json,
+            timestamp_format: TimestampFormat::String,
+            field_naming: FieldNaming::SnakeCase,
+            file_sink: FileSinkConfig::default(),
+            checkpoint: CheckpointConfig::default(),
             event_rate: 1000,
             num_event_generators: 3,
+            bulk: false,
+            side_input: false,
             skip_event_types: String::from(""),
             listen_port: 8000,
+            load_profile: None,
+            config_file: None,
+            properties: vec![],
         }
     }
 }
@@ -1,73 +1,168 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use anyhow::anyhow;
 use anyhow::Result;
+use log::debug;
+use log::error;
 use log::info;
-use log::warn;
 use rdkafka::error::KafkaError;
+use rdkafka::message::OwnedHeaders;
 use rdkafka::producer::{BaseRecord, ProducerContext, ThreadedProducer};
 use rdkafka::types::RDKafkaError;
 use rdkafka::{ClientConfig, ClientContext, Message};
 
+use crate::format::EventEncoder;
 use crate::generator::nexmark::event::Event;
 use crate::generator::source::EnvConfig;
+use crate::metrics;
+use crate::parser::{Durability, Partitioning, ProduceTimestamp, ServerConfig};
+use crate::sink::DestinationCounters;
+
+mod transaction;
 
 const RETRY_BASE_INTERVAL_US: u64 = 1000;
 const RETRY_MAX_INTERVAL_US: u64 = 1000000;
+/// How long the transactions API waits for the brokers.
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
+/// The header holding the time a record is sent at with `--produce-timestamp header`.
+pub const PRODUCE_TS_HEADER: &str = "produce_ts";
 
 pub struct KafkaProducer {
     pub producer: ThreadedProducer<ProduceCallbackLogger>,
     env_config: Arc<EnvConfig>,
-    partition_idx: Option<i32>,
-    key: String,
+    encoder: Arc<EventEncoder>,
+    partitioning: Partitioning,
+    produce_timestamp: ProduceTimestamp,
+    generator_idx: usize,
+    /// The next partition of every event type with `Partitioning::RoundRobin`.
+    next_partitions: [AtomicU64; 3],
+    generator: String,
+    /// Keeps retrying while the queue of the producer is full instead of giving up, as in the
+    /// bulk mode where the generators are only limited by the broker.
+    wait_when_full: bool,
+    transactional: bool,
 }
 
 impl KafkaProducer {
     pub fn new(
         client_config: &ClientConfig,
         env_config: Arc<EnvConfig>,
+        encoder: Arc<EventEncoder>,
+        destinations: Arc<DestinationCounters>,
         generator_idx: usize,
-        generator_num: usize,
+        server_config: &ServerConfig,
     ) -> Self {
+        let transactional = server_config.durability == Durability::Transactional;
+        let mut client_config = client_config.clone();
+        if transactional {
+            // a restarted generator fences off the transactions of its previous instance
+            client_config.set(
+                "transactional.id",
+                &format!("{}-{}", env_config.base_topic, generator_idx),
+            );
+        }
         let producer: ThreadedProducer<ProduceCallbackLogger> = client_config
-            .create_with_context(ProduceCallbackLogger {})
+            .create_with_context(ProduceCallbackLogger {
+                generator: generator_idx.to_string(),
+                destinations,
+                pending: transactional.then(DestinationCounters::default),
+                failures: AtomicU64::new(0),
+            })
             .expect("Failed to create kafka producer");
-        let key = format!("event-{}", generator_idx);
-        let partition_idx = if generator_num as i32 % env_config.num_partitions == 0 {
-            Some(generator_idx as i32 % env_config.num_partitions)
-        } else {
-            None
-        };
+        // the generators start on different partitions
+        let next_partitions = [(); 3].map(|_| AtomicU64::new(generator_idx as u64));
         Self {
             producer,
             env_config,
-            partition_idx,
-            key,
+            encoder,
+            partitioning: server_config.partitioning,
+            produce_timestamp: server_config.produce_timestamp,
+            generator_idx,
+            next_partitions,
+            generator: generator_idx.to_string(),
+            wait_when_full: server_config.bulk,
+            transactional,
         }
     }
 
-    pub async fn send_data_to_topic(&self, data: &String, topic: &str) -> Result<()> {
+    pub fn is_transactional(&self) -> bool {
+        self.transactional
+    }
+
+    /// Registers the `transactional.id` of the producer with the brokers.
+    pub fn init_transactions(&self) -> Result<()> {
+        transaction::init(&self.producer, TRANSACTION_TIMEOUT)
+    }
+
+    pub fn begin_transaction(&self) -> Result<()> {
+        transaction::begin(&self.producer)
+    }
+
+    /// Commits the open transaction, or aborts it if it fails. The events of the transaction
+    /// only count as delivered once it is committed.
+    pub fn commit_transaction(&self) -> Result<()> {
+        let context = self.producer.client().context();
+        let result = transaction::commit(&self.producer, TRANSACTION_TIMEOUT);
+        if result.is_err() {
+            if let Err(err) = transaction::abort(&self.producer, TRANSACTION_TIMEOUT) {
+                error!(
+                    "Producer_{} failed to abort a transaction: {:#}",
+                    self.generator, err
+                );
+            }
+        }
+        if let Some(pending) = &context.pending {
+            let delivered = pending.take();
+            if result.is_ok() {
+                for d in delivered {
+                    context.destinations.add(&d.topic, d.partition, d.events);
+                }
+            }
+        }
+        result
+    }
+
+    pub async fn send_data_to_topic(
+        &self,
+        data: &[u8],
+        topic: &str,
+        key: Option<&str>,
+        partition: Option<i32>,
+        produce_ts: Option<u64>,
+    ) -> Result<()> {
         let mut timeout_us = RETRY_BASE_INTERVAL_US;
         while timeout_us <= RETRY_MAX_INTERVAL_US {
-            let record = if let Some(partition) = self.partition_idx {
-                BaseRecord::<std::string::String, std::string::String>::to(topic)
-                    .key(&self.key)
-                    .partition(partition)
-                    .payload(data)
-            } else {
-                BaseRecord::<std::string::String, std::string::String>::to(topic).payload(data)
-            };
+            let mut record = BaseRecord::<str, [u8]>::to(topic).payload(data);
+            if let Some(key) = key {
+                record = record.key(key);
+            }
+            if let Some(partition) = partition {
+                record = record.partition(partition);
+            }
+            if let (Some(produce_ts), ProduceTimestamp::Header) =
+                (produce_ts, self.produce_timestamp)
+            {
+                record = record
+                    .headers(OwnedHeaders::new().add(PRODUCE_TS_HEADER, &produce_ts.to_string()));
+            }
             let res = self.producer.send(record);
 
             if let Err((e, _)) = res {
                 if let KafkaError::MessageProduction(RDKafkaError::QueueFull) = e {
-                    warn!(
-                        "[Warning] failed to send message to kafka, message: {:?}, err: {:?}, timeout_us for retry: {:?}",
-                        data, e, timeout_us
+                    metrics::QUEUE_FULL_RETRIES
+                        .with_label_values(&[&self.generator])
+                        .inc();
+                    debug!(
+                        "Producer_{} queue is full, retrying in {} us",
+                        self.generator, timeout_us
                     );
                     tokio::time::sleep(Duration::from_micros(timeout_us)).await;
                     timeout_us *= 2;
+                    if self.wait_when_full {
+                        timeout_us = timeout_us.min(RETRY_MAX_INTERVAL_US);
+                    }
                     continue;
                 } else {
                     return Err(anyhow::Error::new(e));
@@ -91,13 +186,66 @@ impl KafkaProducer {
         }
     }
 
-    pub fn serialize_event(&self, event: Event) -> String {
-        event.to_json(!self.env_config.separate_topics)
+    pub fn choose_key(&self, event: &Event) -> Option<String> {
+        match self.partitioning {
+            Partitioning::RoundRobin => None,
+            Partitioning::PerGenerator => Some(format!("event-{}", self.generator_idx)),
+            Partitioning::ByKey => Some(event.entity_id().to_string()),
+        }
+    }
+
+    /// Returns the partition of the event, or `None` to leave it to the partitioner of
+    /// librdkafka.
+    pub fn choose_partition(&self, event: &Event) -> Option<i32> {
+        let num_partitions = self.env_config.num_partitions as u64;
+        match self.partitioning {
+            Partitioning::RoundRobin => {
+                let next = &self.next_partitions[match event {
+                    Event::Person(_) => 0,
+                    Event::Auction(_) => 1,
+                    Event::Bid(_) => 2,
+                }];
+                Some((next.fetch_add(1, Ordering::Relaxed) % num_partitions) as i32)
+            }
+            Partitioning::PerGenerator => Some((self.generator_idx as u64 % num_partitions) as i32),
+            Partitioning::ByKey => None,
+        }
+    }
+
+    /// Returns the wall-clock time in ms since epoch to embed in the next record, if the records
+    /// carry the time they are sent at.
+    pub fn produce_ts(&self) -> Option<u64> {
+        if self.produce_timestamp == ProduceTimestamp::None {
+            return None;
+        }
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
+        Some(now.unwrap_or_default().as_millis() as u64)
+    }
+
+    pub fn serialize_event(&self, event: Event, produce_ts: Option<u64>) -> Result<Vec<u8>> {
+        let produce_ts = produce_ts.filter(|_| self.produce_timestamp == ProduceTimestamp::Field);
+        self.encoder.encode(event, produce_ts)
+    }
+}
+
+/// Logs the messages kafka failed to deliver and counts them for the generator of the producer,
+/// and counts the messages delivered to every partition.
+pub struct ProduceCallbackLogger {
+    generator: String,
+    destinations: Arc<DestinationCounters>,
+    /// The messages delivered in the open transaction, if the producer is transactional.
+    pending: Option<DestinationCounters>,
+    /// Failed deliveries since the last call to `take_failures`.
+    failures: AtomicU64,
+}
+
+impl ProduceCallbackLogger {
+    /// Returns the number of messages that failed to be delivered since the last call.
+    pub fn take_failures(&self) -> u64 {
+        self.failures.swap(0, Ordering::Relaxed)
     }
 }
 
-#[derive(Clone)]
-pub struct ProduceCallbackLogger;
 impl ClientContext for ProduceCallbackLogger {}
 impl ProducerContext for ProduceCallbackLogger {
     type DeliveryOpaque = ();
@@ -106,12 +254,109 @@ impl ProducerContext for ProduceCallbackLogger {
         delivery_result: &rdkafka::producer::DeliveryResult<'_>,
         _delivery_opaque: Self::DeliveryOpaque,
     ) {
-        if let Err(producer_err) = delivery_result {
-            let key: &str = producer_err.1.key_view().unwrap().unwrap();
-            info!(
-                "failed to produce message with key {} - {}",
-                key, producer_err.0,
-            );
-        };
+        match delivery_result {
+            Ok(message) => match &self.pending {
+                Some(pending) => pending.add(message.topic(), message.partition(), 1),
+                None => self
+                    .destinations
+                    .add(message.topic(), message.partition(), 1),
+            },
+            Err(producer_err) => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                metrics::DELIVERY_FAILURES
+                    .with_label_values(&[&self.generator])
+                    .inc();
+                let key = producer_err.1.key_view::<str>().and_then(Result::ok);
+                info!(
+                    "failed to produce message with key {:?} - {}",
+                    key, producer_err.0,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::test_events;
+    use crate::parser::EventFormat;
+
+    fn producer(partitioning: Partitioning, generator_idx: usize) -> KafkaProducer {
+        let env_config: EnvConfig = envy::from_iter(
+            [
+                ("KAFKA_HOST", "127.0.0.1:1"),
+                ("NUM_PARTITIONS", "4"),
+                ("SEPARATE_TOPICS", "true"),
+                ("BASE_TOPIC", "nexmark"),
+                ("AUCTION_TOPIC", "auction"),
+                ("BID_TOPIC", "bid"),
+                ("PERSON_TOPIC", "person"),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string())),
+        )
+        .unwrap();
+        let mut client_config = ClientConfig::new();
+        client_config.set("bootstrap.servers", "127.0.0.1:1");
+        KafkaProducer::new(
+            &client_config,
+            Arc::new(env_config),
+            Arc::new(EventEncoder::new(
+                EventFormat::Json,
+                false,
+                Default::default(),
+            )),
+            Default::default(),
+            generator_idx,
+            &ServerConfig {
+                partitioning,
+                num_event_generators: 2,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_partitioning() {
+        let events = test_events(100);
+        let bids: Vec<_> = events
+            .iter()
+            .filter(|e| matches!(e, Event::Bid(_)))
+            .collect();
+
+        // every event type cycles through the partitions, from the one of the generator
+        let round_robin = producer(Partitioning::RoundRobin, 1);
+        // the persons don't move the bids to the next partition
+        for event in &events {
+            if let Event::Person(_) = event {
+                round_robin.choose_partition(event);
+            }
+        }
+        assert_eq!(
+            bids.iter()
+                .take(5)
+                .map(|e| round_robin.choose_partition(e))
+                .collect::<Vec<_>>(),
+            [Some(1), Some(2), Some(3), Some(0), Some(1)]
+        );
+        assert_eq!(round_robin.choose_key(bids[0]), None);
+
+        let per_generator = producer(Partitioning::PerGenerator, 1);
+        assert!(events
+            .iter()
+            .all(|e| per_generator.choose_partition(e) == Some(1)));
+        assert_eq!(per_generator.choose_key(bids[0]).unwrap(), "event-1");
+
+        // the bids of an auction have the key of the auction
+        let by_key = producer(Partitioning::ByKey, 1);
+        for event in &events {
+            assert_eq!(by_key.choose_partition(event), None);
+            let key = by_key.choose_key(event).unwrap();
+            match event {
+                Event::Person(person) => assert_eq!(key, person.id.to_string()),
+                Event::Auction(auction) => assert_eq!(key, auction.id.to_string()),
+                Event::Bid(bid) => assert_eq!(key, bid.auction.to_string()),
+            }
+        }
     }
 }
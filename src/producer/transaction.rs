@@ -0,0 +1,85 @@
+use std::ffi::CStr;
+use std::os::raw::c_int;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use rdkafka::producer::{ProducerContext, ThreadedProducer};
+use rdkafka_sys as rdsys;
+
+// The transactions API of librdkafka, which `rdkafka` 0.24 doesn't wrap. All the functions block
+// until the brokers answer or the timeout expires.
+
+/// Registers the `transactional.id` of the producer, fencing off the previous producers with it.
+pub fn init<C: ProducerContext>(producer: &ThreadedProducer<C>, timeout: Duration) -> Result<()> {
+    // SAFETY: the native client lives as long as the producer
+    check(unsafe {
+        rdsys::rd_kafka_init_transactions(producer.client().native_ptr(), timeout_ms(timeout))
+    })
+}
+
+pub fn begin<C: ProducerContext>(producer: &ThreadedProducer<C>) -> Result<()> {
+    // SAFETY: the native client lives as long as the producer
+    check(unsafe { rdsys::rd_kafka_begin_transaction(producer.client().native_ptr()) })
+}
+
+/// Flushes the messages of the transaction, then commits it.
+pub fn commit<C: ProducerContext>(producer: &ThreadedProducer<C>, timeout: Duration) -> Result<()> {
+    // SAFETY: the native client lives as long as the producer
+    check(unsafe {
+        rdsys::rd_kafka_commit_transaction(producer.client().native_ptr(), timeout_ms(timeout))
+    })
+}
+
+/// Purges the messages of the transaction that are not delivered yet, then aborts it.
+pub fn abort<C: ProducerContext>(producer: &ThreadedProducer<C>, timeout: Duration) -> Result<()> {
+    // SAFETY: the native client lives as long as the producer
+    check(unsafe {
+        rdsys::rd_kafka_abort_transaction(producer.client().native_ptr(), timeout_ms(timeout))
+    })
+}
+
+fn timeout_ms(timeout: Duration) -> c_int {
+    timeout.as_millis().min(c_int::MAX as u128) as c_int
+}
+
+/// Turns the error returned by the transactions API into an `anyhow::Error`, and frees it.
+fn check(error: *mut rdsys::rd_kafka_error_t) -> Result<()> {
+    if error.is_null() {
+        return Ok(());
+    }
+    // SAFETY: the error is owned by the caller until it is destroyed, and its string with it
+    unsafe {
+        let message = CStr::from_ptr(rdsys::rd_kafka_error_string(error))
+            .to_string_lossy()
+            .into_owned();
+        rdsys::rd_kafka_error_destroy(error);
+        Err(anyhow!(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rdkafka::producer::DefaultProducerContext;
+    use rdkafka::ClientConfig;
+
+    use super::*;
+
+    fn producer(transactional_id: Option<&str>) -> ThreadedProducer<DefaultProducerContext> {
+        let mut config = ClientConfig::new();
+        // nothing listens on the port
+        config.set("bootstrap.servers", "127.0.0.1:1");
+        if let Some(id) = transactional_id {
+            config.set("transactional.id", id);
+        }
+        config.create().unwrap()
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = begin(&producer(None)).unwrap_err();
+        assert!(err.to_string().contains("transactional.id"), "{}", err);
+
+        let producer = producer(Some("nexmark-test"));
+        assert!(init(&producer, Duration::from_millis(100)).is_err());
+    }
+}
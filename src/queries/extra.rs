@@ -0,0 +1,89 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use super::{QueryEngine, QueryResult};
+
+/// The auctions with at least this many bids in q103 and q104.
+const HOT_AUCTION_BIDS: usize = 20;
+/// The auctions kept by the top-N of q105.
+const TOP_AUCTIONS: usize = 1000;
+
+impl QueryEngine {
+    pub(super) fn q101(&self) -> QueryResult {
+        let mut max_prices: HashMap<usize, usize> = HashMap::new();
+        for bid in &self.bids {
+            let max = max_prices.entry(bid.auction).or_default();
+            *max = (*max).max(bid.price);
+        }
+        QueryResult::new(
+            &["auction_id", "auction_item_name", "current_highest_bid"],
+            self.auctions
+                .iter()
+                .map(|a| {
+                    // the left outer join leaves the auctions without bids with a null
+                    let max_price = max_prices.get(&a.id).map(usize::to_string);
+                    row![a.id, a.item_name, max_price.unwrap_or_default()]
+                })
+                .collect(),
+        )
+    }
+
+    pub(super) fn q102(&self) -> QueryResult {
+        let counts = self.bid_counts();
+        let rows = match self.bids.len().checked_div(counts.len()) {
+            Some(average) => self
+                .auctions
+                .iter()
+                .filter_map(|a| {
+                    let count = *counts.get(&a.id)?;
+                    (count >= average).then(|| row![a.id, a.item_name, count])
+                })
+                .collect(),
+            None => vec![],
+        };
+        QueryResult::new(&["auction_id", "auction_item_name", "bid_count"], rows)
+    }
+
+    pub(super) fn q103(&self) -> QueryResult {
+        let counts = self.bid_counts();
+        QueryResult::new(
+            &["auction_id", "auction_item_name"],
+            self.auctions
+                .iter()
+                .filter(|a| counts.get(&a.id).is_some_and(|&n| n >= HOT_AUCTION_BIDS))
+                .map(|a| row![a.id, a.item_name])
+                .collect(),
+        )
+    }
+
+    pub(super) fn q104(&self) -> QueryResult {
+        let counts = self.bid_counts();
+        QueryResult::new(
+            &["auction_id", "auction_item_name"],
+            self.auctions
+                .iter()
+                .filter(|a| counts.get(&a.id).map_or(true, |&n| n >= HOT_AUCTION_BIDS))
+                .map(|a| row![a.id, a.item_name])
+                .collect(),
+        )
+    }
+
+    /// Ties on the last bid count kept are broken by the lowest auction ids.
+    pub(super) fn q105(&self) -> QueryResult {
+        let counts = self.bid_counts();
+        let mut auctions: Vec<_> = self
+            .auctions
+            .iter()
+            .filter_map(|a| Some((*counts.get(&a.id)?, a)))
+            .collect();
+        auctions.sort_by_key(|&(count, a)| (Reverse(count), a.id));
+        auctions.truncate(TOP_AUCTIONS);
+        QueryResult::new(
+            &["auction_id", "auction_item_name", "bid_count"],
+            auctions
+                .into_iter()
+                .map(|(count, a)| row![a.id, a.item_name, count])
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::fs;
+
+use anyhow::{ensure, Context, Result};
+use chrono::{NaiveDateTime, Timelike};
+use log::info;
+
+use crate::generator::config::GeneratorConfig;
+use crate::generator::nexmark::config::NexmarkConfig;
+use crate::generator::nexmark::event::{Auction, Bid, Event, Person};
+use crate::generator::nexmark::side_input::SideInputRow;
+use crate::generator::nexmark::utils::milli_ts_to_timestamp_string;
+use crate::generator::NexmarkGenerator;
+use crate::parser::{ExpectedConfig, ServerConfig};
+
+/// Formats the values of a row of a query result.
+macro_rules! row {
+    ($($value:expr),* $(,)?) => {
+        vec![$($value.to_string()),*]
+    };
+}
+
+mod extra;
+mod standard;
+
+/// The queries of `risingwave/create_sinks.sql`, plus q6 and q11 of the original Nexmark. q12
+/// groups the bids by processing time, so it has no expected result.
+pub const QUERIES: [&str; 27] = [
+    "q0", "q1", "q2", "q3", "q4", "q5", "q6", "q7", "q8", "q9", "q10", "q11", "q13", "q14", "q15",
+    "q16", "q17", "q18", "q19", "q20", "q21", "q22", "q101", "q102", "q103", "q104", "q105",
+];
+
+/// The rows of a query, with the columns of its sink.
+#[derive(Debug)]
+pub struct QueryResult {
+    pub columns: &'static [&'static str],
+    pub rows: Vec<Vec<String>>,
+}
+
+impl QueryResult {
+    fn new(columns: &'static [&'static str], rows: Vec<Vec<String>>) -> Self {
+        Self { columns, rows }
+    }
+
+    /// Encodes the rows as CSV after a header, in the byte order of their lines like
+    /// `LC_ALL=C sort`, so that the output of a system under test only has to be sorted the same
+    /// way to be diffed.
+    pub fn to_csv(&self) -> Result<Vec<u8>> {
+        let mut lines = self
+            .rows
+            .iter()
+            .map(|row| encode_csv_line(row))
+            .collect::<Result<Vec<_>>>()?;
+        lines.sort_unstable();
+        let mut csv = encode_csv_line(self.columns)?;
+        csv.extend(lines.concat());
+        Ok(csv)
+    }
+}
+
+fn encode_csv_line<T: AsRef<[u8]>>(fields: &[T]) -> Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(fields)?;
+    Ok(writer.into_inner()?)
+}
+
+/// Evaluates the Nexmark queries on a bounded run, holding all its events in memory. The results
+/// are the ones a streaming system ends up with once all the events are read and every window is
+/// closed, with the retracted rows removed.
+///
+/// The rows picked arbitrarily by a query, e.g. among bids with the same price in the top-N of
+/// q19, are the first ones generated, so the system under test may differ on them.
+#[derive(Default)]
+pub struct QueryEngine {
+    persons: Vec<Person>,
+    auctions: Vec<Auction>,
+    bids: Vec<Bid>,
+    /// The values of the side input of q13, by key.
+    side_input: HashMap<u64, String>,
+}
+
+impl QueryEngine {
+    pub fn new(side_input: Vec<SideInputRow>) -> Self {
+        Self {
+            side_input: side_input.into_iter().map(|r| (r.key, r.value)).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, event: Event) {
+        match event {
+            Event::Person(person) => self.persons.push(person),
+            Event::Auction(auction) => self.auctions.push(auction),
+            Event::Bid(bid) => self.bids.push(bid),
+        }
+    }
+
+    pub fn evaluate(&self, query: &str) -> Result<QueryResult> {
+        check_query(query)?;
+        let result = match query {
+            "q0" => self.q0(),
+            "q1" => self.q1(),
+            "q2" => self.q2(),
+            "q3" => self.q3(),
+            "q4" => self.q4(),
+            "q5" => self.q5(),
+            "q6" => self.q6(),
+            "q7" => self.q7(),
+            "q8" => self.q8(),
+            "q9" => self.q9(),
+            "q10" => self.q10(),
+            "q11" => self.q11(),
+            "q13" => self.q13(),
+            "q14" => self.q14(),
+            "q15" => self.q15(),
+            "q16" => self.q16(),
+            "q17" => self.q17(),
+            "q18" => self.q18(),
+            "q19" => self.q19(),
+            "q20" => self.q20(),
+            "q21" => self.q21(),
+            "q22" => self.q22(),
+            "q101" => self.q101(),
+            "q102" => self.q102(),
+            "q103" => self.q103(),
+            "q104" => self.q104(),
+            "q105" => self.q105(),
+            _ => unreachable!(),
+        };
+        Ok(result)
+    }
+
+    /// The auctions by id.
+    fn auctions_by_id(&self) -> HashMap<usize, &Auction> {
+        self.auctions.iter().map(|a| (a.id, a)).collect()
+    }
+
+    /// The number of bids on every auction, including the ones never created.
+    fn bid_counts(&self) -> HashMap<usize, usize> {
+        let mut counts = HashMap::new();
+        for bid in &self.bids {
+            *counts.entry(bid.auction).or_default() += 1;
+        }
+        counts
+    }
+}
+
+/// Fails if a query has no expected result.
+pub fn check_query(query: &str) -> Result<()> {
+    ensure!(
+        query != "q12",
+        "q12 groups the bids by processing time, it has no expected result"
+    );
+    ensure!(
+        QUERIES.contains(&query),
+        "unknown query {}, expected one of {}",
+        query,
+        QUERIES.join(",")
+    );
+    Ok(())
+}
+
+/// Generates the events described by the options, from `--start-event` to `--max-events`, and
+/// writes the results of the queries on them to `{output_dir}/{query}.csv`. Returns the number
+/// of rows of every query.
+pub fn run(
+    server_config: &ServerConfig,
+    nexmark_config: NexmarkConfig,
+    expected_config: &ExpectedConfig,
+) -> Result<Vec<(String, usize)>> {
+    ensure!(
+        server_config.max_events > 0,
+        "the expected results need a bounded run, got --max-events 0"
+    );
+    ensure!(
+        !nexmark_config.use_real_time,
+        "the event times follow the wall clock with use_real_time, so the results can't be expected"
+    );
+    let queries: Vec<&str> = match expected_config.queries.as_slice() {
+        [] => QUERIES.to_vec(),
+        queries => queries.iter().map(String::as_str).collect(),
+    };
+    for query in &queries {
+        check_query(query)?;
+    }
+    let mut engine = QueryEngine::new(SideInputRow::generate(&nexmark_config));
+    // the results evaluate every query on all the events, so one generator makes them all
+    let config = GeneratorConfig {
+        start_event: server_config.start_event,
+        ..GeneratorConfig::new(
+            nexmark_config,
+            server_config.max_events,
+            0,
+            1,
+            server_config.skip_event_types.clone(),
+        )
+    };
+    let mut generator = NexmarkGenerator::new(config, 0);
+    while let Some(event) = generator.next_event() {
+        engine.add(event);
+    }
+    info!(
+        "Generated {} persons, {} auctions and {} bids",
+        engine.persons.len(),
+        engine.auctions.len(),
+        engine.bids.len()
+    );
+
+    let output_dir = &expected_config.output_dir;
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("failed to create {}", output_dir.display()))?;
+    let mut row_counts = vec![];
+    for query in queries {
+        let result = engine.evaluate(query)?;
+        let path = output_dir.join(format!("{}.csv", query));
+        fs::write(&path, result.to_csv()?)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        row_counts.push((query.to_string(), result.rows.len()));
+    }
+    Ok(row_counts)
+}
+
+fn date_time(milli_ts: usize) -> NaiveDateTime {
+    NaiveDateTime::from_timestamp_opt(milli_ts as i64 / 1000, (milli_ts % 1000) as u32 * 1_000_000)
+        .unwrap()
+}
+
+fn timestamp(milli_ts: usize) -> String {
+    milli_ts_to_timestamp_string(milli_ts)
+}
+
+/// Formats a timestamp as `to_char` does with the chrono equivalent of a PostgreSQL pattern.
+/// In PostgreSQL, `HH` is the hour of a 12-hour clock and `MI` the minutes, while `mm` is the
+/// month.
+fn to_char(milli_ts: usize, format: &str) -> String {
+    date_time(milli_ts).format(format).to_string()
+}
+
+fn hour(milli_ts: usize) -> u32 {
+    date_time(milli_ts).hour()
+}
+
+/// `0.908 * price` as an exact decimal, in thousandths of the price.
+fn discounted_thousandths(price: usize) -> usize {
+    price * 908
+}
+
+fn discounted(price: usize) -> String {
+    let thousandths = discounted_thousandths(price);
+    format!("{}.{:03}", thousandths / 1000, thousandths % 1000)
+}
+
+/// Rounds the averages to 3 decimals, as they are only approximated by the systems under test.
+fn average(sum: usize, count: usize) -> String {
+    format!("{:.3}", sum as f64 / count as f64)
+}
+
+/// Returns the start of the tumbling window of `size` ms holding a timestamp.
+fn tumble_start(milli_ts: usize, size: usize) -> usize {
+    milli_ts - milli_ts % size
+}
+
+/// Returns the starts of the hopping windows of `size` ms, starting every `slide` ms, that hold a
+/// timestamp.
+fn hop_starts(milli_ts: usize, slide: usize, size: usize) -> impl Iterator<Item = usize> {
+    let first = (milli_ts + 1).saturating_sub(size).div_ceil(slide) * slide;
+    (first..=tumble_start(milli_ts, slide)).step_by(slide)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::test_events;
+
+    fn bid(auction: usize, price: usize, date_time: usize) -> Bid {
+        Bid {
+            auction,
+            bidder: 1,
+            price,
+            channel: "Google".to_string(),
+            url: "https://www.nexmark.com/a/b/c/item.htm?query=1&channel_id=42".to_string(),
+            date_time,
+            extra: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_windows() {
+        assert_eq!(
+            hop_starts(9_999, 2_000, 10_000).collect::<Vec<_>>(),
+            [0, 2_000, 4_000, 6_000, 8_000]
+        );
+        assert_eq!(
+            hop_starts(10_000, 2_000, 10_000).collect::<Vec<_>>(),
+            [2_000, 4_000, 6_000, 8_000, 10_000]
+        );
+
+        let mut engine = QueryEngine::default();
+        // the bid at 10s ends the first window as well as being in the second one
+        for (auction, price, date_time) in [(1, 5, 1_000), (2, 7, 9_000), (3, 7, 10_000)] {
+            engine.add(Event::Bid(bid(auction, price, date_time)));
+        }
+        let q7 = engine.evaluate("q7").unwrap();
+        assert_eq!(q7.rows.len(), 3);
+        let q5 = engine.evaluate("q5").unwrap();
+        // the auctions tie in the windows with several bids, from 0s to 8s
+        assert_eq!(q5.rows.len(), 2 + 4 * 2 + 1);
+        let q21 = engine.evaluate("q21").unwrap();
+        assert_eq!(q21.rows[0], ["1", "1", "5", "Google", "1"]);
+        let q22 = engine.evaluate("q22").unwrap();
+        assert_eq!(q22.rows[0][4..], ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_q6() {
+        let mut engine = QueryEngine::default();
+        for i in 1..=12 {
+            engine.add(Event::Auction(Auction {
+                id: i,
+                item_name: String::new(),
+                description: String::new(),
+                initial_bid: 1,
+                reserve: 1,
+                date_time: i * 1_000,
+                expires: i * 1_000 + 500,
+                seller: 7,
+                category: 10,
+                extra: String::new(),
+            }));
+            engine.add(Event::Bid(bid(i, i * 10, i * 1_000)));
+        }
+        let q6 = engine.evaluate("q6").unwrap();
+        assert_eq!(q6.rows.len(), 12);
+        assert_eq!(q6.rows[9], ["7", &average(550, 10)]);
+        // the last 10 auctions, from the 3rd to the 12th
+        assert_eq!(q6.rows[11], ["7", &average(750, 10)]);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut engine = QueryEngine::default();
+        for event in test_events(2000) {
+            engine.add(event);
+        }
+        for query in QUERIES {
+            let result = engine.evaluate(query).unwrap();
+            assert!(result.rows.iter().all(|r| r.len() == result.columns.len()));
+        }
+        let q0 = engine.evaluate("q0").unwrap();
+        assert_eq!(q0.rows.len(), engine.bids.len());
+        let csv = String::from_utf8(q0.to_csv().unwrap()).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], "auction,bidder,price,date_time");
+        assert!(lines[1..].windows(2).all(|pair| pair[0] <= pair[1]));
+        // one row per auction with a bid while it is open
+        assert_eq!(
+            engine.evaluate("q6").unwrap().rows.len(),
+            engine.evaluate("q9").unwrap().rows.len()
+        );
+        let q101 = engine.evaluate("q101").unwrap();
+        assert_eq!(q101.rows.len(), engine.auctions.len());
+        assert!(engine.evaluate("q12").is_err());
+        assert!(engine.evaluate("q23").is_err());
+    }
+}
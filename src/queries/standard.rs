@@ -0,0 +1,662 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::{
+    average, discounted, discounted_thousandths, hop_starts, hour, timestamp, to_char,
+    tumble_start, QueryEngine, QueryResult,
+};
+use crate::generator::nexmark::event::{Auction, Bid};
+
+const SECOND: usize = 1000;
+const DAY: &str = "%Y-%m-%d";
+
+/// Counts the bids of q15 and q16, in total and in the ranks of their prices.
+#[derive(Default)]
+struct RankedCounts {
+    bids: [usize; 4],
+    bidders: [HashSet<usize>; 4],
+    auctions: [HashSet<usize>; 4],
+}
+
+impl RankedCounts {
+    fn add(&mut self, bid: &Bid) {
+        for rank in [0, price_rank(bid.price)] {
+            self.bids[rank] += 1;
+            self.bidders[rank].insert(bid.bidder);
+            self.auctions[rank].insert(bid.auction);
+        }
+    }
+
+    fn values(&self) -> impl Iterator<Item = String> + '_ {
+        let bidders = self.bidders.iter().map(HashSet::len);
+        let auctions = self.auctions.iter().map(HashSet::len);
+        self.bids
+            .into_iter()
+            .chain(bidders)
+            .chain(auctions)
+            .map(|count| count.to_string())
+    }
+}
+
+/// Ranks a price below 10000, below 1000000, or above.
+fn price_rank(price: usize) -> usize {
+    match price {
+        0..=9_999 => 1,
+        10_000..=999_999 => 2,
+        _ => 3,
+    }
+}
+
+/// The `channel_id` parameter of the url of a bid, matched by `(&|^)channel_id=([^&]*)`.
+fn channel_id(url: &str) -> Option<&str> {
+    url.split('&')
+        .find_map(|param| param.strip_prefix("channel_id="))
+}
+
+/// The `n`th part of the url split on `/`, counted from 1, as `split_part`.
+fn split_part(url: &str, n: usize) -> &str {
+    url.split('/').nth(n - 1).unwrap_or_default()
+}
+
+impl QueryEngine {
+    /// The highest bid on every auction while it is open, the earliest one among the bids with
+    /// the same price. Keyed by auction id.
+    fn winning_bids(&self) -> BTreeMap<usize, (&Auction, &Bid)> {
+        let auctions = self.auctions_by_id();
+        let mut winning: BTreeMap<usize, (&Auction, &Bid)> = BTreeMap::new();
+        for bid in &self.bids {
+            let Some(&auction) = auctions.get(&bid.auction) else {
+                continue;
+            };
+            if !(auction.date_time..=auction.expires).contains(&bid.date_time) {
+                continue;
+            }
+            let (_, best) = winning.entry(auction.id).or_insert((auction, bid));
+            if (bid.price, std::cmp::Reverse(bid.date_time))
+                > (best.price, std::cmp::Reverse(best.date_time))
+            {
+                *best = bid;
+            }
+        }
+        winning
+    }
+
+    pub(super) fn q0(&self) -> QueryResult {
+        QueryResult::new(
+            &["auction", "bidder", "price", "date_time"],
+            self.bids
+                .iter()
+                .map(|b| row![b.auction, b.bidder, b.price, timestamp(b.date_time)])
+                .collect(),
+        )
+    }
+
+    pub(super) fn q1(&self) -> QueryResult {
+        QueryResult::new(
+            &["auction", "bidder", "price", "date_time"],
+            self.bids
+                .iter()
+                .map(|b| {
+                    row![
+                        b.auction,
+                        b.bidder,
+                        discounted(b.price),
+                        timestamp(b.date_time)
+                    ]
+                })
+                .collect(),
+        )
+    }
+
+    pub(super) fn q2(&self) -> QueryResult {
+        QueryResult::new(
+            &["auction", "price"],
+            self.bids
+                .iter()
+                .filter(|b| [1007, 1020, 2001, 2019, 2087].contains(&b.auction))
+                .map(|b| row![b.auction, b.price])
+                .collect(),
+        )
+    }
+
+    pub(super) fn q3(&self) -> QueryResult {
+        let persons: HashMap<_, _> = self
+            .persons
+            .iter()
+            .filter(|p| ["or", "id", "ca"].contains(&p.state.as_str()))
+            .map(|p| (p.id, p))
+            .collect();
+        QueryResult::new(
+            &["name", "city", "state", "id"],
+            self.auctions
+                .iter()
+                .filter(|a| a.category == 10)
+                .filter_map(|a| {
+                    let p = persons.get(&a.seller)?;
+                    Some(row![p.name, p.city, p.state, a.id])
+                })
+                .collect(),
+        )
+    }
+
+    pub(super) fn q4(&self) -> QueryResult {
+        let mut finals: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+        for (auction, bid) in self.winning_bids().into_values() {
+            let (sum, count) = finals.entry(auction.category).or_default();
+            *sum += bid.price;
+            *count += 1;
+        }
+        QueryResult::new(
+            &["category", "avg"],
+            finals
+                .into_iter()
+                .map(|(category, (sum, count))| row![category, average(sum, count)])
+                .collect(),
+        )
+    }
+
+    pub(super) fn q5(&self) -> QueryResult {
+        let mut counts: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+        for bid in &self.bids {
+            for start in hop_starts(bid.date_time, 2 * SECOND, 10 * SECOND) {
+                *counts.entry((start, bid.auction)).or_default() += 1;
+            }
+        }
+        let mut max_counts: HashMap<usize, usize> = HashMap::new();
+        for (&(start, _), &num) in &counts {
+            let max = max_counts.entry(start).or_default();
+            *max = (*max).max(num);
+        }
+        QueryResult::new(
+            &["auction", "num"],
+            counts
+                .into_iter()
+                .filter(|((start, _), num)| *num >= max_counts[start])
+                .map(|((_, auction), num)| row![auction, num])
+                .collect(),
+        )
+    }
+
+    /// The average of the final prices of the last 10 auctions of the seller, by the time they
+    /// started, as of every auction.
+    pub(super) fn q6(&self) -> QueryResult {
+        let mut by_seller: BTreeMap<usize, Vec<(usize, usize, usize)>> = BTreeMap::new();
+        for (auction, bid) in self.winning_bids().into_values() {
+            by_seller.entry(auction.seller).or_default().push((
+                auction.date_time,
+                auction.id,
+                bid.price,
+            ));
+        }
+        let mut rows = vec![];
+        for (seller, mut finals) in by_seller {
+            finals.sort_unstable();
+            for i in 0..finals.len() {
+                let window = &finals[(i + 1).saturating_sub(10)..=i];
+                let sum = window.iter().map(|&(_, _, price)| price).sum();
+                rows.push(row![seller, average(sum, window.len())]);
+            }
+        }
+        QueryResult::new(&["seller", "avg"], rows)
+    }
+
+    pub(super) fn q7(&self) -> QueryResult {
+        let window = 10 * SECOND;
+        let mut max_prices: HashMap<usize, usize> = HashMap::new();
+        for bid in &self.bids {
+            let end = tumble_start(bid.date_time, window) + window;
+            let max = max_prices.entry(end).or_default();
+            *max = (*max).max(bid.price);
+        }
+        let mut rows = vec![];
+        for bid in &self.bids {
+            let end = tumble_start(bid.date_time, window) + window;
+            // `BETWEEN` also matches the bids at the end of the previous window
+            let previous_end = (bid.date_time % window == 0).then_some(bid.date_time);
+            for end in [Some(end), previous_end].into_iter().flatten() {
+                if max_prices.get(&end) == Some(&bid.price) {
+                    rows.push(row![
+                        bid.auction,
+                        bid.price,
+                        bid.bidder,
+                        timestamp(bid.date_time)
+                    ]);
+                }
+            }
+        }
+        QueryResult::new(&["auction", "price", "bidder", "date_time"], rows)
+    }
+
+    pub(super) fn q8(&self) -> QueryResult {
+        let window = 10 * SECOND;
+        let sellers: HashSet<_> = self
+            .auctions
+            .iter()
+            .map(|a| (a.seller, tumble_start(a.date_time, window)))
+            .collect();
+        let persons: HashSet<_> = self
+            .persons
+            .iter()
+            .map(|p| (p.id, p.name.as_str(), tumble_start(p.date_time, window)))
+            .filter(|&(id, _, start)| sellers.contains(&(id, start)))
+            .collect();
+        QueryResult::new(
+            &["id", "name", "starttime"],
+            persons
+                .into_iter()
+                .map(|(id, name, start)| row![id, name, timestamp(start)])
+                .collect(),
+        )
+    }
+
+    pub(super) fn q9(&self) -> QueryResult {
+        QueryResult::new(
+            &[
+                "id",
+                "item_name",
+                "description",
+                "initial_bid",
+                "reserve",
+                "date_time",
+                "expires",
+                "seller",
+                "category",
+                "auction",
+                "bidder",
+                "price",
+                "bid_date_time",
+            ],
+            self.winning_bids()
+                .into_values()
+                .map(|(a, b)| {
+                    row![
+                        a.id,
+                        a.item_name,
+                        a.description,
+                        a.initial_bid,
+                        a.reserve,
+                        timestamp(a.date_time),
+                        timestamp(a.expires),
+                        a.seller,
+                        a.category,
+                        b.auction,
+                        b.bidder,
+                        b.price,
+                        timestamp(b.date_time),
+                    ]
+                })
+                .collect(),
+        )
+    }
+
+    pub(super) fn q10(&self) -> QueryResult {
+        QueryResult::new(
+            &["auction", "bidder", "price", "date_time", "date", "time"],
+            self.bids
+                .iter()
+                .map(|b| {
+                    row![
+                        b.auction,
+                        b.bidder,
+                        b.price,
+                        timestamp(b.date_time),
+                        to_char(b.date_time, DAY),
+                        // 'HH:MI'
+                        to_char(b.date_time, "%I:%M"),
+                    ]
+                })
+                .collect(),
+        )
+    }
+
+    /// The sessions of the bidders, closed after 10 seconds without bids, as in the original
+    /// Nexmark.
+    pub(super) fn q11(&self) -> QueryResult {
+        let gap = 10 * SECOND;
+        let mut times: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for bid in &self.bids {
+            times.entry(bid.bidder).or_default().push(bid.date_time);
+        }
+        let mut rows = vec![];
+        for (bidder, mut times) in times {
+            times.sort_unstable();
+            let mut start = 0;
+            for i in 1..=times.len() {
+                if i == times.len() || times[i] - times[i - 1] >= gap {
+                    rows.push(row![
+                        bidder,
+                        i - start,
+                        timestamp(times[start]),
+                        timestamp(times[i - 1] + gap)
+                    ]);
+                    start = i;
+                }
+            }
+        }
+        QueryResult::new(&["bidder", "bid_count", "starttime", "endtime"], rows)
+    }
+
+    pub(super) fn q13(&self) -> QueryResult {
+        QueryResult::new(
+            &["auction", "bidder", "price", "date_time", "value"],
+            self.bids
+                .iter()
+                .filter_map(|b| {
+                    let value = self.side_input.get(&(b.auction as u64 % 10000))?;
+                    Some(row![
+                        b.auction,
+                        b.bidder,
+                        b.price,
+                        timestamp(b.date_time),
+                        value
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    pub(super) fn q14(&self) -> QueryResult {
+        QueryResult::new(
+            &["auction", "bidder", "price", "bidtimetype", "date_time"],
+            self.bids
+                .iter()
+                .filter(|b| {
+                    let thousandths = discounted_thousandths(b.price);
+                    1_000_000_000 < thousandths && thousandths < 50_000_000_000
+                })
+                .map(|b| {
+                    let bid_time_type = match hour(b.date_time) {
+                        8..=18 => "dayTime",
+                        0..=6 | 20.. => "nightTime",
+                        _ => "otherTime",
+                    };
+                    row![
+                        b.auction,
+                        b.bidder,
+                        discounted(b.price),
+                        bid_time_type,
+                        timestamp(b.date_time)
+                    ]
+                })
+                .collect(),
+        )
+    }
+
+    pub(super) fn q15(&self) -> QueryResult {
+        let mut days: BTreeMap<String, RankedCounts> = BTreeMap::new();
+        for bid in &self.bids {
+            days.entry(to_char(bid.date_time, DAY))
+                .or_default()
+                .add(bid);
+        }
+        QueryResult::new(
+            &[
+                "day",
+                "total_bids",
+                "rank1_bids",
+                "rank2_bids",
+                "rank3_bids",
+                "total_bidders",
+                "rank1_bidders",
+                "rank2_bidders",
+                "rank3_bidders",
+                "total_auctions",
+                "rank1_auctions",
+                "rank2_auctions",
+                "rank3_auctions",
+            ],
+            days.into_iter()
+                .map(|(day, counts)| std::iter::once(day).chain(counts.values()).collect())
+                .collect(),
+        )
+    }
+
+    pub(super) fn q16(&self) -> QueryResult {
+        let mut groups: BTreeMap<(&str, String), (String, RankedCounts)> = BTreeMap::new();
+        for bid in &self.bids {
+            let key = (bid.channel.as_str(), to_char(bid.date_time, DAY));
+            let (minute, counts) = groups.entry(key).or_default();
+            // 'HH:mm'
+            let bid_minute = to_char(bid.date_time, "%I:%m");
+            if bid_minute > *minute {
+                *minute = bid_minute;
+            }
+            counts.add(bid);
+        }
+        QueryResult::new(
+            &[
+                "channel",
+                "day",
+                "minute",
+                "total_bids",
+                "rank1_bids",
+                "rank2_bids",
+                "rank3_bids",
+                "total_bidders",
+                "rank1_bidders",
+                "rank2_bidders",
+                "rank3_bidders",
+                "total_auctions",
+                "rank1_auctions",
+                "rank2_auctions",
+                "rank3_auctions",
+            ],
+            groups
+                .into_iter()
+                .map(|((channel, day), (minute, counts))| {
+                    row![channel, day, minute]
+                        .into_iter()
+                        .chain(counts.values())
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    pub(super) fn q17(&self) -> QueryResult {
+        #[derive(Default)]
+        struct Stats {
+            bids: [usize; 4],
+            min: usize,
+            max: usize,
+            sum: usize,
+        }
+        let mut groups: BTreeMap<(usize, String), Stats> = BTreeMap::new();
+        for bid in &self.bids {
+            let key = (bid.auction, to_char(bid.date_time, DAY));
+            let stats = groups.entry(key).or_insert_with(|| Stats {
+                min: usize::MAX,
+                ..Default::default()
+            });
+            stats.bids[0] += 1;
+            stats.bids[price_rank(bid.price)] += 1;
+            stats.min = stats.min.min(bid.price);
+            stats.max = stats.max.max(bid.price);
+            stats.sum += bid.price;
+        }
+        QueryResult::new(
+            &[
+                "auction",
+                "day",
+                "total_bids",
+                "rank1_bids",
+                "rank2_bids",
+                "rank3_bids",
+                "min_price",
+                "max_price",
+                "avg_price",
+                "sum_price",
+            ],
+            groups
+                .into_iter()
+                .map(|((auction, day), s)| {
+                    row![
+                        auction,
+                        day,
+                        s.bids[0],
+                        s.bids[1],
+                        s.bids[2],
+                        s.bids[3],
+                        s.min,
+                        s.max,
+                        average(s.sum, s.bids[0]),
+                        s.sum,
+                    ]
+                })
+                .collect(),
+        )
+    }
+
+    pub(super) fn q18(&self) -> QueryResult {
+        let mut latest: HashMap<(usize, usize), &Bid> = HashMap::new();
+        for bid in &self.bids {
+            let last = latest.entry((bid.bidder, bid.auction)).or_insert(bid);
+            if bid.date_time > last.date_time {
+                *last = bid;
+            }
+        }
+        QueryResult::new(
+            &["auction", "bidder", "price", "channel", "url", "date_time"],
+            latest
+                .into_values()
+                .map(|b| {
+                    row![
+                        b.auction,
+                        b.bidder,
+                        b.price,
+                        b.channel,
+                        b.url,
+                        timestamp(b.date_time)
+                    ]
+                })
+                .collect(),
+        )
+    }
+
+    pub(super) fn q19(&self) -> QueryResult {
+        let mut by_auction: HashMap<usize, Vec<&Bid>> = HashMap::new();
+        for bid in &self.bids {
+            by_auction.entry(bid.auction).or_default().push(bid);
+        }
+        let mut rows = vec![];
+        for mut bids in by_auction.into_values() {
+            bids.sort_by_key(|b| std::cmp::Reverse(b.price));
+            rows.extend(bids.into_iter().take(10).map(|b| {
+                row![
+                    b.auction,
+                    b.bidder,
+                    b.price,
+                    b.channel,
+                    b.url,
+                    timestamp(b.date_time),
+                    b.extra
+                ]
+            }));
+        }
+        QueryResult::new(
+            &[
+                "auction",
+                "bidder",
+                "price",
+                "channel",
+                "url",
+                "date_time",
+                "extra",
+            ],
+            rows,
+        )
+    }
+
+    pub(super) fn q20(&self) -> QueryResult {
+        let auctions: HashMap<_, _> = self
+            .auctions
+            .iter()
+            .filter(|a| a.category == 10)
+            .map(|a| (a.id, a))
+            .collect();
+        QueryResult::new(
+            &[
+                "auction",
+                "bidder",
+                "price",
+                "channel",
+                "url",
+                "bid_date_time",
+                "bid_extra",
+                "item_name",
+                "description",
+                "initial_bid",
+                "reserve",
+                "auction_date_time",
+                "expires",
+                "seller",
+                "category",
+                "auction_extra",
+            ],
+            self.bids
+                .iter()
+                .filter_map(|b| {
+                    let a = auctions.get(&b.auction)?;
+                    Some(row![
+                        b.auction,
+                        b.bidder,
+                        b.price,
+                        b.channel,
+                        b.url,
+                        timestamp(b.date_time),
+                        b.extra,
+                        a.item_name,
+                        a.description,
+                        a.initial_bid,
+                        a.reserve,
+                        timestamp(a.date_time),
+                        timestamp(a.expires),
+                        a.seller,
+                        a.category,
+                        a.extra,
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    pub(super) fn q21(&self) -> QueryResult {
+        QueryResult::new(
+            &["auction", "bidder", "price", "channel", "channel_id"],
+            self.bids
+                .iter()
+                .filter_map(|b| {
+                    let channel_id = match b.channel.to_lowercase().as_str() {
+                        "apple" => "0",
+                        "google" => "1",
+                        "facebook" => "2",
+                        "baidu" => "3",
+                        _ => channel_id(&b.url)?,
+                    };
+                    Some(row![b.auction, b.bidder, b.price, b.channel, channel_id])
+                })
+                .collect(),
+        )
+    }
+
+    pub(super) fn q22(&self) -> QueryResult {
+        QueryResult::new(
+            &[
+                "auction", "bidder", "price", "channel", "dir1", "dir2", "dir3",
+            ],
+            self.bids
+                .iter()
+                .map(|b| {
+                    row![
+                        b.auction,
+                        b.bidder,
+                        b.price,
+                        b.channel,
+                        split_part(&b.url, 4),
+                        split_part(&b.url, 5),
+                        split_part(&b.url, 6)
+                    ]
+                })
+                .collect(),
+        )
+    }
+}
@@ -1,11 +1,12 @@
-use std::sync::{atomic::Ordering, Arc};
+use std::sync::Arc;
 
-use rocket::{post, response::status, serde::json::Json, State};
-use serde::Deserialize;
+use rocket::{get, post, response::status, serde::json::Json, State};
+use serde::{Deserialize, Serialize};
 
-use crate::generator::config::GeneratorConfig;
+use crate::load_profile::{LoadProfile, LoadProfileScheduler};
+use crate::metrics;
 use crate::parser::ServerConfig;
-use crate::NexmarkInterval;
+use crate::{GeneratorControl, NexmarkInterval};
 
 #[derive(Deserialize)]
 #[serde(crate = "rocket::serde")]
@@ -17,14 +18,135 @@ pub struct QPSHandler {
 pub fn qps(
     qps_handler: Json<QPSHandler>,
     interval_state: &State<Arc<NexmarkInterval>>,
-    conf_state: &State<ServerConfig>,
+    scheduler_state: &State<Arc<LoadProfileScheduler>>,
 ) -> status::Accepted<std::string::String> {
-    interval_state.microseconds.store(
-        GeneratorConfig::get_event_delay_microseconds(
-            qps_handler.qps,
-            conf_state.num_event_generators,
-        ),
-        Ordering::Relaxed,
-    );
+    // a fixed rate replaces the running load profile or rate shape
+    scheduler_state.stop();
+    interval_state.set_rate(qps_handler.qps as f64);
     status::Accepted(format!("qps: {}", qps_handler.qps))
 }
+
+/// Exposes the metrics of the generators in the Prometheus text format.
+#[get("/metrics")]
+pub fn prometheus_metrics() -> String {
+    metrics::gather()
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct GeneratorStatus {
+    /// `running`, `paused` or `stopping`.
+    pub state: &'static str,
+    pub uptime_secs: f64,
+    /// The events per second of all the generators together.
+    pub target_rate: f64,
+    /// The average delay between the events of a generator.
+    pub interval_us: f64,
+    pub skip_event_types: String,
+    pub events_per_generator: Vec<u64>,
+    pub events_produced: u64,
+    /// Absent when the number of events is unlimited.
+    pub max_events: Option<u64>,
+    /// Counts the events skipped by `--start-event` as done.
+    pub progress: Option<f64>,
+}
+
+#[get("/status")]
+pub fn generator_status(
+    control_state: &State<Arc<GeneratorControl>>,
+    interval_state: &State<Arc<NexmarkInterval>>,
+    conf_state: &State<ServerConfig>,
+) -> Json<GeneratorStatus> {
+    let state = if !control_state.is_running() {
+        "stopping"
+    } else if control_state.is_paused() {
+        "paused"
+    } else {
+        "running"
+    };
+    let (skip_person, skip_auction, skip_bid) = control_state.skipped_event_types();
+    let skip_event_types = [
+        (skip_person, "person"),
+        (skip_auction, "auction"),
+        (skip_bid, "bid"),
+    ]
+    .iter()
+    .filter(|(skip, _)| *skip)
+    .map(|(_, event_type)| *event_type)
+    .collect::<Vec<_>>()
+    .join(",");
+    let target_rate = interval_state.rate();
+    let events_per_generator = control_state.events_produced();
+    let events_produced = events_per_generator.iter().sum();
+    let max_events = (conf_state.max_events > 0).then_some(conf_state.max_events);
+    Json(GeneratorStatus {
+        state,
+        uptime_secs: control_state.uptime().as_secs_f64(),
+        target_rate,
+        interval_us: 1_000_000.0 * conf_state.num_event_generators as f64 / target_rate,
+        skip_event_types,
+        events_per_generator,
+        events_produced,
+        max_events,
+        progress: max_events.map(|max_events| {
+            (conf_state.start_event + events_produced) as f64 / max_events as f64
+        }),
+    })
+}
+
+#[post("/pause")]
+pub fn pause(control_state: &State<Arc<GeneratorControl>>) -> status::Accepted<String> {
+    control_state.set_paused(true);
+    status::Accepted("paused".to_string())
+}
+
+#[post("/resume")]
+pub fn resume(control_state: &State<Arc<GeneratorControl>>) -> status::Accepted<String> {
+    control_state.set_paused(false);
+    status::Accepted("resumed".to_string())
+}
+
+/// Stops the generators, which flush the sink before the server exits.
+#[post("/stop")]
+pub fn stop(control_state: &State<Arc<GeneratorControl>>) -> status::Accepted<String> {
+    control_state.stop();
+    status::Accepted("stopping".to_string())
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SkipEventTypesHandler {
+    pub skip_event_types: String,
+}
+
+#[post("/skip_event_types", data = "<handler>")]
+pub fn skip_event_types(
+    handler: Json<SkipEventTypesHandler>,
+    control_state: &State<Arc<GeneratorControl>>,
+) -> Result<status::Accepted<String>, status::BadRequest<String>> {
+    match control_state.set_skip_event_types(&handler.skip_event_types) {
+        Ok(()) => Ok(status::Accepted(format!(
+            "skip_event_types: {}",
+            handler.skip_event_types
+        ))),
+        Err(err) => Err(status::BadRequest(err.to_string())),
+    }
+}
+
+/// Starts a load profile, replacing the running one.
+#[post("/load_profile", data = "<profile>")]
+pub fn load_profile(
+    profile: Json<LoadProfile>,
+    scheduler_state: &State<Arc<LoadProfileScheduler>>,
+) -> Result<status::Accepted<String>, status::BadRequest<String>> {
+    let profile = profile.into_inner();
+    if let Err(err) = profile.validate() {
+        return Err(status::BadRequest(err.to_string()));
+    }
+    let phases = profile.phases.len();
+    scheduler_state.start(profile);
+    Ok(status::Accepted(format!(
+        "load profile with {} phases started",
+        phases
+    )))
+}
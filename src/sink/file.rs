@@ -0,0 +1,474 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use flate2::write::GzEncoder;
+use log::info;
+use serde::Serialize;
+
+use crate::format::view::{AuctionView, BidView, PersonView};
+use crate::format::SerializeOptions;
+use crate::generator::nexmark::event::Event;
+use crate::generator::nexmark::side_input::SideInputRow;
+use crate::parser::{FileCompression, FileFormat, FileSinkConfig};
+use crate::sink::{DestinationCount, DestinationCounters, Sink, SinkCounters, SinkMetrics};
+
+const COMBINED_STREAM: &str = "events";
+const SIDE_INPUT_FILE: &str = "side_input";
+
+/// Writes the events to local files, sharded by generator and optionally by event type.
+///
+/// Files are named `{stream}-{generator_idx}-{sequence}.{format}[.gz|.zst]`, where `stream` is
+/// `person`, `auction`, `bid`, or `events` when the event types are combined, and `sequence`
+/// is bumped every time the file is rotated. The side input is written uncompressed to
+/// `side_input.{format}`, without a header to be read by the filesystem connector of Flink.
+pub struct FileSink {
+    writer: Arc<EventWriter>,
+    generators: Vec<Arc<Mutex<HashMap<&'static str, RotatingFile>>>>,
+    counters: SinkCounters,
+    destinations: DestinationCounters,
+}
+
+impl FileSink {
+    pub fn new(
+        config: &FileSinkConfig,
+        num_event_generators: usize,
+        options: SerializeOptions,
+    ) -> Self {
+        Self {
+            writer: Arc::new(EventWriter {
+                config: config.clone(),
+                options,
+            }),
+            generators: (0..num_event_generators)
+                .map(|_| Arc::new(Mutex::new(HashMap::new())))
+                .collect(),
+            counters: SinkCounters::default(),
+            destinations: DestinationCounters::default(),
+        }
+    }
+}
+
+/// Encodes the events and writes them to the files of their generator, off the async runtime
+/// since the writes and the compression block.
+struct EventWriter {
+    config: FileSinkConfig,
+    options: SerializeOptions,
+}
+
+impl EventWriter {
+    fn stream(&self, event: &Event) -> &'static str {
+        if self.config.separate_files {
+            event.event_type()
+        } else {
+            COMBINED_STREAM
+        }
+    }
+
+    /// Encodes an event as one line, with the header of the file if it is the first one.
+    fn encode(&self, event: Event, with_header: bool) -> Result<Vec<u8>> {
+        match self.config.file_format {
+            FileFormat::Jsonl => {
+                let mut line = event
+                    .to_json(!self.config.separate_files, self.options)
+                    .into_bytes();
+                line.push(b'\n');
+                Ok(line)
+            }
+            // combined csv files have no header since rows have different columns, the first
+            // one is the event type, as `event_type` in the combined json
+            FileFormat::Csv if !self.config.separate_files => match &event {
+                Event::Person(p) => encode_csv_row(&(0, PersonView(p, self.options)), false),
+                Event::Auction(a) => encode_csv_row(&(1, AuctionView(a, self.options)), false),
+                Event::Bid(b) => encode_csv_row(&(2, BidView(b, self.options)), false),
+            },
+            FileFormat::Csv => match &event {
+                Event::Person(p) => encode_csv_row(&PersonView(p, self.options), with_header),
+                Event::Auction(a) => encode_csv_row(&AuctionView(a, self.options), with_header),
+                Event::Bid(b) => encode_csv_row(&BidView(b, self.options), with_header),
+            },
+        }
+    }
+
+    fn write_event(
+        &self,
+        files: &mut HashMap<&'static str, RotatingFile>,
+        generator_idx: usize,
+        event: Event,
+    ) -> Result<usize> {
+        let stream = self.stream(&event);
+        let file = files
+            .entry(stream)
+            .or_insert_with(|| RotatingFile::new(&self.config, stream, generator_idx));
+        file.rotate_if_full(&self.config)?;
+        let data = self.encode(event, file.is_empty())?;
+        file.write(&data)?;
+        Ok(data.len())
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    async fn open(&self) -> Result<()> {
+        let config = &self.writer.config;
+        std::fs::create_dir_all(&config.output_dir).with_context(|| {
+            format!(
+                "failed to create output dir {}",
+                config.output_dir.display()
+            )
+        })?;
+        info!("Writing events to {}", config.output_dir.display());
+        Ok(())
+    }
+
+    async fn send_side_input(&self, rows: &[SideInputRow]) -> Result<()> {
+        let config = &self.writer.config;
+        let path = config.output_dir.join(format!(
+            "{}.{}",
+            SIDE_INPUT_FILE,
+            format_extension(config.file_format)
+        ));
+        let mut file = BufWriter::new(
+            File::create(&path).with_context(|| format!("failed to create {}", path.display()))?,
+        );
+        for row in rows {
+            match config.file_format {
+                FileFormat::Jsonl => writeln!(file, "{}", row.to_json())?,
+                FileFormat::Csv => file.write_all(&encode_csv_row(row, false)?)?,
+            }
+        }
+        file.into_inner()?.sync_all()?;
+        info!("Wrote the side input to {}", path.display());
+        Ok(())
+    }
+
+    async fn send_batch(&self, generator_idx: usize, events: Vec<Event>) -> Result<()> {
+        let (writer, files) = (self.writer.clone(), self.generators[generator_idx].clone());
+        let writes = tokio::task::spawn_blocking(move || {
+            let mut files = files.lock().unwrap();
+            events
+                .into_iter()
+                .map(|event| {
+                    let event_type = event.event_type();
+                    let stream = writer.stream(&event);
+                    let bytes = writer.write_event(&mut files, generator_idx, event);
+                    (event_type, stream, bytes)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await?;
+        let mut result = Ok(());
+        let mut written = HashMap::<&'static str, u64>::new();
+        for (event_type, stream, bytes) in writes {
+            match bytes {
+                Ok(bytes) => {
+                    self.counters.record_sent(generator_idx, event_type, bytes);
+                    *written.entry(stream).or_default() += 1;
+                }
+                Err(err) => {
+                    self.counters.record_error(generator_idx, event_type);
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                }
+            }
+        }
+        for (stream, events) in written {
+            self.destinations.add(stream, generator_idx as i32, events);
+        }
+        result
+    }
+
+    fn flush(&self, generator_idx: usize, _timeout: Duration) -> Result<()> {
+        let mut files = self.generators[generator_idx].lock().unwrap();
+        for file in files.values_mut() {
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        let generators = self.generators.clone();
+        tokio::task::spawn_blocking(move || {
+            for files in generators {
+                for (_, mut file) in files.lock().unwrap().drain() {
+                    file.finish()?;
+                }
+            }
+            Ok(())
+        })
+        .await?
+    }
+
+    fn metrics(&self) -> SinkMetrics {
+        self.counters.snapshot()
+    }
+
+    fn destinations(&self) -> Vec<DestinationCount> {
+        self.destinations.snapshot()
+    }
+}
+
+fn format_extension(format: FileFormat) -> &'static str {
+    match format {
+        FileFormat::Jsonl => "jsonl",
+        FileFormat::Csv => "csv",
+    }
+}
+
+fn encode_csv_row<T: Serialize>(record: &T, with_header: bool) -> Result<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(with_header)
+        .from_writer(vec![]);
+    writer.serialize(record)?;
+    Ok(writer.into_inner()?)
+}
+
+/// The file currently written for a stream of a generator.
+struct RotatingFile {
+    prefix: PathBuf,
+    extension: String,
+    compression: FileCompression,
+    sequence: usize,
+    writer: Option<FileWriter>,
+    events: u64,
+    bytes: u64,
+}
+
+impl RotatingFile {
+    fn new(config: &FileSinkConfig, stream: &str, generator_idx: usize) -> Self {
+        let format = format_extension(config.file_format);
+        let extension = match config.compression {
+            FileCompression::None => format.to_string(),
+            FileCompression::Gzip => format!("{}.gz", format),
+            FileCompression::Zstd => format!("{}.zst", format),
+        };
+        Self {
+            prefix: config
+                .output_dir
+                .join(format!("{}-{}", stream, generator_idx)),
+            extension,
+            compression: config.compression,
+            sequence: 0,
+            writer: None,
+            events: 0,
+            bytes: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.events == 0
+    }
+
+    fn rotate_if_full(&mut self, config: &FileSinkConfig) -> Result<()> {
+        let full = (config.rotate_events > 0 && self.events >= config.rotate_events)
+            || (config.rotate_bytes > 0 && self.bytes >= config.rotate_bytes);
+        if full {
+            self.finish()?;
+            self.sequence += 1;
+            self.events = 0;
+            self.bytes = 0;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => {
+                let path = PathBuf::from(format!(
+                    "{}-{:05}.{}",
+                    self.prefix.display(),
+                    self.sequence,
+                    self.extension
+                ));
+                self.writer
+                    .insert(FileWriter::create(&path, self.compression)?)
+            }
+        };
+        writer.write_all(data)?;
+        self.events += 1;
+        self.bytes += data.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.writer {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.writer.take() {
+            Some(writer) => writer.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+enum FileWriter {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl FileWriter {
+    fn create(path: &Path, compression: FileCompression) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create file {}", path.display()))?;
+        let file = BufWriter::new(file);
+        Ok(match compression {
+            FileCompression::None => FileWriter::Plain(file),
+            FileCompression::Gzip => {
+                FileWriter::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+            }
+            FileCompression::Zstd => FileWriter::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    /// Writes the trailer of the compressed formats and flushes the file.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            FileWriter::Plain(mut file) => file.flush(),
+            FileWriter::Gzip(encoder) => encoder.finish()?.flush(),
+            FileWriter::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            FileWriter::Plain(file) => file.write(buf),
+            FileWriter::Gzip(encoder) => encoder.write(buf),
+            FileWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FileWriter::Plain(file) => file.flush(),
+            FileWriter::Gzip(encoder) => encoder.flush(),
+            FileWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::generator::test_events;
+
+    /// Writes the first `max_events` events to a new output dir, closing the files.
+    async fn write_events(config: FileSinkConfig, max_events: u64) -> (TempDir, FileSink) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = FileSinkConfig {
+            output_dir: dir.path().to_path_buf(),
+            ..config
+        };
+        let sink = FileSink::new(&config, 1, SerializeOptions::default());
+        sink.open().await.unwrap();
+        sink.send_batch(0, test_events(max_events)).await.unwrap();
+        sink.close().await.unwrap();
+        (dir, sink)
+    }
+
+    fn read_files(dir: &Path, prefix: &str) -> Vec<String> {
+        let mut paths = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.file_name()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .starts_with(prefix)
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| {
+                let mut content = String::new();
+                if path.extension().unwrap() == "gz" {
+                    GzDecoder::new(File::open(path).unwrap())
+                        .read_to_string(&mut content)
+                        .unwrap();
+                } else {
+                    content = std::fs::read_to_string(path).unwrap();
+                }
+                content
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_rotated_gzip_jsonl() {
+        let config = FileSinkConfig {
+            rotate_events: 40,
+            compression: FileCompression::Gzip,
+            ..Default::default()
+        };
+        let (dir, sink) = write_events(config, 100).await;
+
+        let files = read_files(dir.path(), "events-0-");
+        assert_eq!(
+            files.iter().map(|f| f.lines().count()).collect::<Vec<_>>(),
+            vec![40, 40, 20]
+        );
+        assert!(files[0].starts_with(r#"{"event_type":0,"person":{"id":1000"#));
+        assert_eq!(sink.metrics().events_sent, 100);
+    }
+
+    #[tokio::test]
+    async fn test_separate_csv() {
+        let config = FileSinkConfig {
+            file_format: FileFormat::Csv,
+            separate_files: true,
+            ..Default::default()
+        };
+        let (dir, sink) = write_events(config, 100).await;
+
+        let persons = read_files(dir.path(), "person-0-");
+        let mut lines = persons[0].lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "id,name,email_address,credit_card,city,state,date_time,extra"
+        );
+        assert_eq!(lines.count(), 2);
+        let bids = read_files(dir.path(), "bid-0-");
+        assert_eq!(bids[0].lines().count(), 1 + 92);
+        assert_eq!(
+            sink.destinations()
+                .iter()
+                .map(|d| (d.topic.as_str(), d.events))
+                .collect::<Vec<_>>(),
+            vec![("auction", 6), ("bid", 92), ("person", 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_combined_csv() {
+        let config = FileSinkConfig {
+            file_format: FileFormat::Csv,
+            ..Default::default()
+        };
+        let (dir, _) = write_events(config, 2).await;
+
+        let events = read_files(dir.path(), "events-0-");
+        let mut lines = events[0].lines();
+        assert!(lines.next().unwrap().starts_with("0,1000,"));
+        assert!(lines.next().unwrap().starts_with("1,1000,"));
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+
+use crate::format::SerializeOptions;
+use crate::generator::nexmark::event::Event;
+use crate::generator::nexmark::side_input::SideInputRow;
+use crate::generator::source::NexmarkSource;
+use crate::metrics;
+use crate::parser::{ServerConfig, SinkType};
+use crate::sink::file::FileSink;
+
+pub mod file;
+
+/// A target the generated events are sent to, e.g. kafka.
+///
+/// Every generator is identified by its index, so that implementations can keep one producer
+/// or file per generator.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    /// Prepares the sink before the generators start, e.g. checks that the topics exist.
+    async fn open(&self) -> Result<()>;
+
+    /// Writes the side input of Query 13 and waits until it is delivered, before the generators
+    /// start.
+    async fn send_side_input(&self, _rows: &[SideInputRow]) -> Result<()> {
+        bail!("the side input is not supported by this sink")
+    }
+
+    /// Sends the events produced by a generator. All the events are attempted even if some of
+    /// them fail, in which case the first error is returned.
+    async fn send_batch(&self, generator_idx: usize, events: Vec<Event>) -> Result<()>;
+
+    /// Waits until the events sent by a generator are delivered. Fails if some of them are still
+    /// in flight, or failed to be delivered since the last flush.
+    /// It may block, so it is called off the async runtime.
+    fn flush(&self, generator_idx: usize, timeout: Duration) -> Result<()>;
+
+    /// Releases the sink once all the generators are done.
+    async fn close(&self) -> Result<()>;
+
+    /// Returns the counters of the sink since it was created.
+    fn metrics(&self) -> SinkMetrics;
+
+    /// Returns the number of events delivered to every destination, e.g. every partition of the
+    /// kafka topics, if the sink tracks them.
+    fn destinations(&self) -> Vec<DestinationCount> {
+        vec![]
+    }
+}
+
+/// Creates the sink selected by `--sink`.
+pub fn build_sink(config: &ServerConfig) -> Arc<dyn Sink> {
+    match config.sink {
+        SinkType::Kafka => Arc::new(NexmarkSource::new(config)),
+        SinkType::Stdout => Arc::new(StdoutSink::new(config.serialize_options())),
+        SinkType::File => Arc::new(FileSink::new(
+            &config.file_sink,
+            config.num_event_generators,
+            config.serialize_options(),
+        )),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SinkMetrics {
+    pub events_sent: u64,
+    /// The events confirmed by the sink, e.g. by the delivery reports of kafka, which only
+    /// confirm that the events were sent with `--durability fast`.
+    pub events_delivered: u64,
+    pub bytes_sent: u64,
+    pub send_errors: u64,
+}
+
+/// Thread-safe counters shared by the generators to fill `SinkMetrics`, also exported to
+/// Prometheus by generator and event type.
+#[derive(Debug, Default)]
+pub struct SinkCounters {
+    events_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    send_errors: AtomicU64,
+}
+
+impl SinkCounters {
+    pub fn record_sent(&self, generator_idx: usize, event_type: &str, bytes: usize) {
+        self.events_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        let labels = [&generator_idx.to_string(), event_type];
+        metrics::EVENTS_SENT.with_label_values(&labels).inc();
+        metrics::BYTES_SERIALIZED
+            .with_label_values(&labels)
+            .inc_by(bytes as u64);
+    }
+
+    pub fn record_error(&self, generator_idx: usize, event_type: &str) {
+        self.send_errors.fetch_add(1, Ordering::Relaxed);
+        metrics::SEND_ERRORS
+            .with_label_values(&[&generator_idx.to_string(), event_type])
+            .inc();
+    }
+
+    /// Returns the counters, where all the events sent are delivered as for the sinks writing
+    /// synchronously.
+    pub fn snapshot(&self) -> SinkMetrics {
+        let events_sent = self.events_sent.load(Ordering::Relaxed);
+        SinkMetrics {
+            events_sent,
+            events_delivered: events_sent,
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            send_errors: self.send_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The events delivered to a partition of a topic, or to the files of a stream written by a
+/// generator for the file sink.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DestinationCount {
+    pub topic: String,
+    pub partition: i32,
+    pub events: u64,
+}
+
+impl fmt::Display for DestinationCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}]: {} events",
+            self.topic, self.partition, self.events
+        )
+    }
+}
+
+/// Reports how evenly the events of every topic are spread over its partitions, given the counts
+/// sorted by topic as returned by `Sink::destinations`.
+pub struct Distribution<'a>(pub &'a [DestinationCount]);
+
+impl fmt::Display for Distribution<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rest = self.0;
+        while let Some(first) = rest.first() {
+            let len = rest.iter().take_while(|d| d.topic == first.topic).count();
+            let (topic, next) = rest.split_at(len);
+            rest = next;
+            let total: u64 = topic.iter().map(|d| d.events).sum();
+            let max = topic.iter().map(|d| d.events).max().unwrap_or_default();
+            let mean = total as f64 / topic.len() as f64;
+            writeln!(
+                f,
+                "{}: {} events over {} partitions, the largest with {:.2}x the mean",
+                first.topic,
+                total,
+                topic.len(),
+                if total == 0 { 0.0 } else { max as f64 / mean },
+            )?;
+            for destination in topic {
+                writeln!(
+                    f,
+                    "  {} ({:.1}%)",
+                    destination,
+                    destination.events as f64 * 100.0 / total.max(1) as f64
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Thread-safe counters of the events delivered to every destination.
+#[derive(Debug, Default)]
+pub struct DestinationCounters {
+    topics: Mutex<BTreeMap<String, BTreeMap<i32, u64>>>,
+}
+
+impl DestinationCounters {
+    pub fn add(&self, topic: &str, partition: i32, events: u64) {
+        let mut topics = self.topics.lock().unwrap();
+        let partitions = match topics.get_mut(topic) {
+            Some(partitions) => partitions,
+            None => topics.entry(topic.to_string()).or_default(),
+        };
+        *partitions.entry(partition).or_default() += events;
+    }
+
+    /// Returns the counts and resets them.
+    pub fn take(&self) -> Vec<DestinationCount> {
+        let topics = std::mem::take(&mut *self.topics.lock().unwrap());
+        Self::counts(&topics)
+    }
+
+    /// Returns the counts sorted by topic and partition.
+    pub fn snapshot(&self) -> Vec<DestinationCount> {
+        Self::counts(&self.topics.lock().unwrap())
+    }
+
+    fn counts(topics: &BTreeMap<String, BTreeMap<i32, u64>>) -> Vec<DestinationCount> {
+        topics
+            .iter()
+            .flat_map(|(topic, partitions)| {
+                partitions
+                    .iter()
+                    .map(move |(&partition, &events)| DestinationCount {
+                        topic: topic.clone(),
+                        partition,
+                        events,
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Prints every event as a line of JSON, in the combined layout so that the event type is kept.
+#[derive(Debug, Default)]
+pub struct StdoutSink {
+    options: SerializeOptions,
+    counters: SinkCounters,
+}
+
+impl StdoutSink {
+    pub fn new(options: SerializeOptions) -> Self {
+        Self {
+            options,
+            counters: SinkCounters::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for StdoutSink {
+    async fn open(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn send_side_input(&self, rows: &[SideInputRow]) -> Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        for row in rows {
+            writeln!(stdout, "{}", row.to_json())?;
+        }
+        Ok(stdout.flush()?)
+    }
+
+    async fn send_batch(&self, generator_idx: usize, events: Vec<Event>) -> Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        let mut result = Ok(());
+        for event in events {
+            let line = event.to_json(true, self.options);
+            match writeln!(stdout, "{}", line) {
+                Ok(()) => self
+                    .counters
+                    .record_sent(generator_idx, event.event_type(), line.len()),
+                Err(err) => {
+                    self.counters
+                        .record_error(generator_idx, event.event_type());
+                    if result.is_ok() {
+                        result = Err(err.into());
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn flush(&self, _generator_idx: usize, _timeout: Duration) -> Result<()> {
+        Ok(std::io::stdout().flush()?)
+    }
+
+    async fn close(&self) -> Result<()> {
+        Ok(std::io::stdout().flush()?)
+    }
+
+    fn metrics(&self) -> SinkMetrics {
+        self.counters.snapshot()
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use anyhow::{ensure, Context, Result};
+use log::info;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::{ClientConfig, Message, Offset, TopicPartitionList};
+
+use crate::format::decode::decode_json;
+use crate::format::SerializeOptions;
+use crate::generator::nexmark::config::NexmarkConfig;
+use crate::generator::nexmark::event::Event;
+use crate::generator::reorder::{DisorderMeter, DisorderStats};
+use crate::generator::source::{consumer_config, NexmarkSource};
+use crate::parser::{EventFormat, ServerConfig, VerifyConfig};
+
+const KAFKA_GET_METADATA_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How many invalid records are described in the report.
+const MAX_SAMPLES: usize = 5;
+const EVENT_TYPES: [&str; 3] = ["person", "auction", "bid"];
+
+/// Reads back the topics the events are sent to, up to the end they had when it started, and
+/// checks them with an `EventChecker`.
+pub async fn run(
+    server_config: ServerConfig,
+    nexmark_config: NexmarkConfig,
+    verify_config: VerifyConfig,
+) -> Result<VerifyReport> {
+    ensure!(
+        server_config.format == EventFormat::Json,
+        "verify only reads the json format"
+    );
+    // the consumer blocks while polling
+    tokio::task::spawn_blocking(move || {
+        let env_config = NexmarkSource::load_env();
+        let consumer_config = consumer_config(&env_config)?;
+        let topics: Vec<(&str, Option<&str>)> = if env_config.separate_topics {
+            env_config
+                .topics()
+                .into_iter()
+                .zip(EVENT_TYPES.map(Some))
+                .collect()
+        } else {
+            vec![(&env_config.base_topic, None)]
+        };
+        let mut checker = EventChecker::new(
+            server_config.serialize_options(),
+            nexmark_config.max_delay_ms as u64,
+        );
+        read_topics(
+            &consumer_config,
+            &topics,
+            &mut checker,
+            Duration::from_secs(verify_config.idle_timeout_secs),
+        )?;
+        let expected = (server_config.max_events > 0).then(|| {
+            expected_counts(
+                &nexmark_config,
+                server_config.start_event,
+                server_config.max_events,
+                &server_config.skip_event_types,
+            )
+        });
+        Ok(checker.report(expected))
+    })
+    .await?
+}
+
+/// Feeds every record of the topics, paired with the event type of the separate topics, to the
+/// checker. The partitions are read up to their end when it starts, so that the events
+/// produced meanwhile are left out.
+fn read_topics(
+    consumer_config: &ClientConfig,
+    topics: &[(&str, Option<&str>)],
+    checker: &mut EventChecker,
+    idle_timeout: Duration,
+) -> Result<()> {
+    let consumer: BaseConsumer = consumer_config
+        .create()
+        .context("failed to create kafka consumer")?;
+    let mut assignment = TopicPartitionList::new();
+    let mut ends = HashMap::new();
+    for &(topic, event_type) in topics {
+        let metadata = consumer
+            .fetch_metadata(Some(topic), KAFKA_GET_METADATA_TIMEOUT)
+            .with_context(|| format!("failed to fetch the metadata of {}", topic))?;
+        let partitions = match metadata.topics() {
+            [meta] if !meta.partitions().is_empty() => meta.partitions(),
+            _ => anyhow::bail!("topic {} not found", topic),
+        };
+        for partition in partitions {
+            let (low, high) =
+                consumer.fetch_watermarks(topic, partition.id(), KAFKA_GET_METADATA_TIMEOUT)?;
+            if high > low {
+                assignment.add_partition_offset(topic, partition.id(), Offset::Beginning);
+                ends.insert((topic.to_string(), partition.id()), (high, event_type));
+            }
+        }
+    }
+    info!("Reading {} partitions", ends.len());
+    consumer.assign(&assignment)?;
+    let mut last_record = Instant::now();
+    while !ends.is_empty() {
+        match consumer.poll(POLL_INTERVAL) {
+            Some(message) => {
+                let message = message.context("failed to read a record")?;
+                let key = (message.topic().to_string(), message.partition());
+                let Some(&(end, event_type)) = ends.get(&key) else {
+                    continue;
+                };
+                let event = decode_json(
+                    message.payload().unwrap_or_default(),
+                    event_type,
+                    checker.options,
+                );
+                let destination = format!("{}/{}", key.0, key.1);
+                checker.add(&destination, message.offset(), event);
+                if message.offset() + 1 >= end {
+                    ends.remove(&key);
+                }
+                last_record = Instant::now();
+            }
+            None => {
+                // the markers of the transactions at the end of a partition aren't returned
+                for position in consumer.position()?.elements() {
+                    if let Offset::Offset(offset) = position.offset() {
+                        let key = (position.topic().to_string(), position.partition());
+                        if ends.get(&key).is_some_and(|&(end, _)| offset >= end) {
+                            ends.remove(&key);
+                        }
+                    }
+                }
+                ensure!(
+                    ends.is_empty() || last_record.elapsed() < idle_timeout,
+                    "no record read for {:?}, {} partitions are not read to the end",
+                    idle_timeout,
+                    ends.len()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the persons, auctions and bids generated from the event id `start_event` up to
+/// `max_events`.
+pub fn expected_counts(
+    nexmark_config: &NexmarkConfig,
+    start_event: u64,
+    max_events: u64,
+    skip_event_types: &str,
+) -> [u64; 3] {
+    let mut counts = [0; 3];
+    for event_id in start_event..max_events {
+        let event_type = nexmark_config.event_type(event_id as usize);
+        if !skip_event_types.contains(event_type) {
+            counts[type_index(event_type)] += 1;
+        }
+    }
+    counts
+}
+
+fn type_index(event_type: &str) -> usize {
+    EVENT_TYPES.iter().position(|&t| t == event_type).unwrap()
+}
+
+/// The persons or auctions read, and the references to them.
+#[derive(Default)]
+struct Entities {
+    /// The event time of every entity, by id.
+    created: HashMap<usize, usize>,
+    duplicates: u64,
+    /// The earliest event time of the events referencing every id, and how many do.
+    references: HashMap<usize, (usize, u64)>,
+}
+
+impl Entities {
+    fn create(&mut self, id: usize, date_time: usize) {
+        if self.created.insert(id, date_time).is_some() {
+            self.duplicates += 1;
+        }
+    }
+
+    fn reference(&mut self, id: usize, date_time: usize) {
+        let (earliest, count) = self.references.entry(id).or_insert((date_time, 0));
+        *earliest = (*earliest).min(date_time);
+        *count += 1;
+    }
+
+    /// Returns the lowest and highest ids, if any.
+    fn id_range(&self) -> Option<(usize, usize)> {
+        let min = *self.created.keys().min()?;
+        Some((min, *self.created.keys().max()?))
+    }
+
+    /// Returns the ids missing between the lowest and the highest one.
+    fn missing(&self) -> usize {
+        self.id_range()
+            .map_or(0, |(min, max)| max - min + 1 - self.created.len())
+    }
+
+    /// Returns how many events reference a missing id, and how many ids are only created after
+    /// the first event referencing them. Ids beyond the ones read may be created later and are
+    /// left out.
+    fn dangling_and_forward_references(&self) -> (u64, u64) {
+        let Some((min, max)) = self.id_range() else {
+            return (0, 0);
+        };
+        let (mut dangling, mut forward) = (0, 0);
+        for (id, &(earliest, count)) in &self.references {
+            match self.created.get(id) {
+                Some(&created) => forward += (created > earliest) as u64,
+                None if (min..=max).contains(id) => dangling += count,
+                None => {}
+            }
+        }
+        (dangling, forward)
+    }
+
+    /// Returns how many entities have an earlier event time than the one with the previous id.
+    fn timestamp_regressions(&self) -> usize {
+        let mut created: Vec<_> = self.created.iter().collect();
+        created.sort_unstable();
+        created
+            .windows(2)
+            .filter(|pair| pair[1].1 < pair[0].1)
+            .count()
+    }
+}
+
+/// Checks the events read back from the sink: that they are valid records, that none are lost
+/// or duplicated, and that the ids and timestamps are consistent.
+pub struct EventChecker {
+    options: SerializeOptions,
+    max_delay_ms: u64,
+    counts: [u64; 3],
+    invalid: u64,
+    samples: Vec<String>,
+    persons: Entities,
+    auctions: Entities,
+    expired_auctions: u64,
+    /// The disorder of the event times in every destination, e.g. a kafka partition.
+    disorder: BTreeMap<String, DisorderMeter>,
+}
+
+impl EventChecker {
+    /// `options` are the ones the events were written with, and `max_delay_ms` the bound of the
+    /// disorder of their event times.
+    pub fn new(options: SerializeOptions, max_delay_ms: u64) -> Self {
+        Self {
+            options,
+            max_delay_ms,
+            counts: [0; 3],
+            invalid: 0,
+            samples: vec![],
+            persons: Entities::default(),
+            auctions: Entities::default(),
+            expired_auctions: 0,
+            disorder: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the event read at an offset of a destination, or the reason it is invalid.
+    pub fn add(&mut self, destination: &str, offset: i64, event: Result<Event>) {
+        let event = match event {
+            Ok(event) => event,
+            Err(err) => {
+                self.invalid += 1;
+                if self.samples.len() < MAX_SAMPLES {
+                    self.samples
+                        .push(format!("{} at {}: {:#}", destination, offset, err));
+                }
+                return;
+            }
+        };
+        self.counts[type_index(event.event_type())] += 1;
+        match &event {
+            Event::Person(person) => self.persons.create(person.id, person.date_time),
+            Event::Auction(auction) => {
+                self.auctions.create(auction.id, auction.date_time);
+                self.persons.reference(auction.seller, auction.date_time);
+                self.expired_auctions += (auction.expires <= auction.date_time) as u64;
+            }
+            Event::Bid(bid) => {
+                self.auctions.reference(bid.auction, bid.date_time);
+                self.persons.reference(bid.bidder, bid.date_time);
+            }
+        }
+        let max_delay_ms = self.max_delay_ms;
+        self.disorder
+            .entry(destination.to_string())
+            .or_insert_with(|| DisorderMeter::unlabeled(max_delay_ms))
+            .record(&event);
+    }
+
+    /// Returns the outcome of the checks, comparing the counts with the `expected` ones if known.
+    pub fn report(self, expected: Option<[u64; 3]>) -> VerifyReport {
+        let mut schema = vec![];
+        if self.invalid > 0 {
+            schema.push(format!("{} invalid records, e.g.:", self.invalid));
+            schema.extend(self.samples.iter().map(|sample| format!("  {}", sample)));
+        }
+
+        let mut counts = vec![];
+        for ((event_type, count), expected) in EVENT_TYPES
+            .iter()
+            .zip(self.counts)
+            .zip(expected.unwrap_or(self.counts))
+        {
+            if count != expected {
+                counts.push(format!(
+                    "expected {} {}s, got {}",
+                    expected, event_type, count
+                ));
+            }
+        }
+
+        let mut ids = vec![];
+        let mut references = vec![];
+        let mut timestamps = vec![];
+        let mut notes = vec![];
+        for (name, entities) in [("person", &self.persons), ("auction", &self.auctions)] {
+            if entities.duplicates > 0 {
+                ids.push(format!("{} duplicated {} ids", entities.duplicates, name));
+            }
+            if let Some((min, max)) = entities.id_range() {
+                let missing = entities.missing();
+                if missing > 0 {
+                    ids.push(format!(
+                        "{} {} ids missing between {} and {}",
+                        missing, name, min, max
+                    ));
+                }
+            }
+            let (dangling, forward) = entities.dangling_and_forward_references();
+            if dangling > 0 {
+                references.push(format!("{} events reference a missing {}", dangling, name));
+            }
+            if forward > 0 {
+                notes.push(format!(
+                    "{} {}s are referenced before they are created, as allowed by {}_id_lead",
+                    forward, name, name
+                ));
+            }
+            let regressions = entities.timestamp_regressions();
+            if regressions > 0 {
+                timestamps.push(format!(
+                    "{} {}s are older than the {} with the previous id",
+                    regressions, name, name
+                ));
+            }
+        }
+        if self.expired_auctions > 0 {
+            timestamps.push(format!(
+                "{} auctions expire before they start",
+                self.expired_auctions
+            ));
+        }
+        if expected.is_none() {
+            notes.push("the counts are not checked without --max-events".to_string());
+        }
+
+        VerifyReport {
+            counts: self.counts,
+            checks: vec![
+                ("schema", schema),
+                ("counts", counts),
+                ("ids", ids),
+                ("references", references),
+                ("timestamps", timestamps),
+            ],
+            notes,
+            disorder: self
+                .disorder
+                .into_iter()
+                .map(|(destination, meter)| (destination, meter.stats()))
+                .collect(),
+        }
+    }
+}
+
+/// The outcome of `nexmark-server verify`.
+#[derive(Clone, Debug)]
+pub struct VerifyReport {
+    /// The persons, auctions and bids read.
+    pub counts: [u64; 3],
+    /// The failures of every check.
+    pub checks: Vec<(&'static str, Vec<String>)>,
+    pub notes: Vec<String>,
+    pub disorder: Vec<(String, DisorderStats)>,
+}
+
+impl VerifyReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|(_, failures)| failures.is_empty())
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [persons, auctions, bids] = self.counts;
+        writeln!(
+            f,
+            "Read {} persons, {} auctions and {} bids",
+            persons, auctions, bids
+        )?;
+        for (name, failures) in &self.checks {
+            if failures.is_empty() {
+                writeln!(f, "{}: ok", name)?;
+            } else {
+                writeln!(f, "{}: FAILED", name)?;
+                for failure in failures {
+                    writeln!(f, "  {}", failure)?;
+                }
+            }
+        }
+        for note in &self.notes {
+            writeln!(f, "Note: {}", note)?;
+        }
+        writeln!(f, "Disorder of the event times:")?;
+        for (destination, stats) in &self.disorder {
+            writeln!(f, "  {}: {}", destination, stats)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::nexmark::properties::NexmarkProperties;
+    use crate::generator::test_events;
+
+    fn check(events: &[Event], payloads: &[&[u8]], expected: [u64; 3]) -> VerifyReport {
+        let options = SerializeOptions::default();
+        let mut checker = EventChecker::new(options, 0);
+        for (offset, event) in events.iter().enumerate() {
+            let payload = event.to_json(true, options);
+            checker.add(
+                "nexmark/0",
+                offset as i64,
+                decode_json(payload.as_bytes(), None, options),
+            );
+        }
+        for payload in payloads {
+            checker.add("nexmark/1", 0, decode_json(payload, None, options));
+        }
+        checker.report(Some(expected))
+    }
+
+    #[test]
+    fn test_verify() {
+        let nexmark_config = NexmarkConfig::from(NexmarkProperties::default()).unwrap();
+        let expected = expected_counts(&nexmark_config, 0, 10_000, "");
+        assert_eq!(expected, [200, 600, 9200]);
+        let mut events = test_events(10_000);
+        let report = check(&events, &[], expected);
+        assert!(report.passed(), "{}", report);
+        assert!(
+            report
+                .notes
+                .iter()
+                .any(|note| note.contains("auctions are referenced before")),
+            "{}",
+            report
+        );
+
+        // a lost person, a duplicated auction and an invalid record
+        let (lost, _) = events
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| matches!(e, Event::Person(_)))
+            .nth(100)
+            .unwrap();
+        events.remove(lost);
+        let auction = events
+            .iter()
+            .find(|e| matches!(e, Event::Auction(_)))
+            .unwrap();
+        events.push(auction.clone());
+        let report = check(&events, &[b"{}"], expected);
+        assert!(!report.passed());
+        let failures: HashMap<_, _> = report.checks.into_iter().collect();
+        assert_eq!(failures["schema"].len(), 2);
+        assert_eq!(
+            failures["counts"],
+            [
+                "expected 200 persons, got 199",
+                "expected 600 auctions, got 601"
+            ]
+        );
+        assert_eq!(failures["ids"].len(), 2);
+        assert_eq!(failures["references"].len(), 1);
+        assert!(failures["timestamps"].is_empty());
+    }
+}